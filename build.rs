@@ -0,0 +1,13 @@
+fn main() {
+    #[cfg(feature = "ffi")]
+    {
+        println!("cargo:rerun-if-changed=src/ffi.rs");
+        println!("cargo:rerun-if-changed=cbindgen.toml");
+        // the build must not write into the sources, a test keeps include/cluna.h in sync
+        let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+        let out_dir = std::env::var("OUT_DIR").unwrap();
+        cbindgen::generate(crate_dir)
+            .expect("Unable to generate the C header")
+            .write_to_file(std::path::Path::new(&out_dir).join("cluna.h"));
+    }
+}
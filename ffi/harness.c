@@ -0,0 +1,71 @@
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+
+#include "cluna.h"
+
+static struct cluna_result *convert(const char *source, const struct cluna_options *options) {
+    struct cluna_result *result = cluna_convert(source, strlen(source), options);
+    assert(result != NULL);
+    return result;
+}
+
+static void converts_source(void) {
+    struct cluna_result *result = convert("local a = 1", NULL);
+    assert(result->code != NULL);
+    assert(strlen(result->code) == result->code_len);
+    assert(strstr(result->code, "local a = 1") != NULL);
+    assert(result->diagnostics_len == 0);
+    cluna_result_free(result);
+}
+
+static void reports_errors(void) {
+    struct cluna_options options = cluna_options_default();
+    options.path = "broken.lua";
+    struct cluna_result *result = convert("local x = 1\nprint(x +)\n", &options);
+    assert(result->code == NULL);
+    assert(result->diagnostics_len > 0);
+    struct cluna_diagnostic *error = &result->diagnostics[result->diagnostics_len - 1];
+    assert(error->level == CLUNA_LEVEL_ERROR);
+    assert(strcmp(error->path, "broken.lua") == 0);
+    assert(error->line == 2);
+    assert(strstr(error->rendered, "print(x +)") != NULL);
+    cluna_result_free(result);
+}
+
+static void reports_warnings(void) {
+    struct cluna_result *result = convert("local a <const> = 1", NULL);
+    assert(result->code != NULL);
+    assert(result->diagnostics_len > 0);
+    assert(result->diagnostics[0].level == CLUNA_LEVEL_WARNING);
+    assert(result->diagnostics[0].path == NULL);
+    cluna_result_free(result);
+}
+
+static void uses_options(void) {
+    struct cluna_options options = cluna_options_default();
+    options.dialect = CLUNA_DIALECT_LUAU;
+    options.compound_assignment = true;
+    struct cluna_result *result = convert("local a: number = 1\na = a + 1", &options);
+    assert(result->code != NULL);
+    assert(strstr(result->code, "a += 1") != NULL);
+    cluna_result_free(result);
+}
+
+static void rejects_invalid_utf8(void) {
+    struct cluna_result *result = cluna_convert("\xff", 1, NULL);
+    assert(result->code == NULL);
+    assert(result->diagnostics_len == 1);
+    cluna_result_free(result);
+}
+
+int main(void) {
+    converts_source();
+    reports_errors();
+    reports_warnings();
+    uses_options();
+    rejects_invalid_utf8();
+    cluna_result_free(NULL);
+    puts("ok");
+    return 0;
+}
@@ -0,0 +1,94 @@
+#ifndef CLUNA_H
+#define CLUNA_H
+
+/* Generated by cbindgen from src/ffi.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+typedef enum cluna_dialect {
+  CLUNA_DIALECT_LUA,
+  CLUNA_DIALECT_LUAU,
+  CLUNA_DIALECT_GLUA,
+} cluna_dialect;
+
+typedef enum cluna_level {
+  CLUNA_LEVEL_ERROR,
+  CLUNA_LEVEL_WARNING,
+  CLUNA_LEVEL_NOTE,
+} cluna_level;
+
+typedef struct cluna_options {
+  enum cluna_dialect dialect;
+  /**
+   * nul terminated, may be null, only used to point diagnostics at the file
+   */
+  const char *path;
+  bool preserve_types;
+  bool compound_assignment;
+  bool ternary;
+  bool safe_navigation;
+  bool match_statements;
+  bool enums;
+  bool continue_statements;
+  bool default_parameters;
+} cluna_options;
+
+/**
+ * every string is nul terminated, the optional ones are null when missing
+ * line and column start from 1, all the position fields are 0 when the diagnostic has no position
+ */
+typedef struct cluna_diagnostic {
+  enum cluna_level level;
+  char *message;
+  char *hint;
+  char *path;
+  size_t line;
+  size_t column;
+  size_t start;
+  size_t end;
+  /**
+   * the diagnostic as the cli would print it, without colors
+   */
+  char *rendered;
+} cluna_diagnostic;
+
+typedef struct cluna_result {
+  /**
+   * null when the source could not be converted, the last diagnostic says why
+   * nul terminated, code_len doesn't count the terminator
+   */
+  char *code;
+  size_t code_len;
+  struct cluna_diagnostic *diagnostics;
+  size_t diagnostics_len;
+} cluna_result;
+
+/**
+ * Returns the options `cluna_convert` uses when it is given null options.
+ */
+struct cluna_options cluna_options_default(void);
+
+/**
+ * Converts `len` bytes of UTF-8 Lua source at `src`, with the default options when `options` is null.
+ *
+ * # Safety
+ * `src` must point to `len` readable bytes and `options` must be null or point to valid options.
+ * The result is never null and must be released with `cluna_result_free`.
+ */
+struct cluna_result *cluna_convert(const char *src,
+                                   size_t len,
+                                   const struct cluna_options *options);
+
+/**
+ * Releases a result and everything it owns, does nothing when `result` is null.
+ *
+ * # Safety
+ * `result` must be null or come from `cluna_convert`, and must not be used after this call.
+ */
+void cluna_result_free(struct cluna_result *result);
+
+#endif /* CLUNA_H */
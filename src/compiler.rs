@@ -1,8 +1,106 @@
-use std::iter::Peekable;
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use std::{
+    cell::RefCell,
+    collections::{HashSet, VecDeque},
+    iter::Peekable,
+    sync::Arc,
+};
 
-use crate::parser::{CodeBlock, ComplexToken, Expression};
+use crate::{
+    error::{Diagnostic, DiagnosticLevel},
+    idioms,
+    lexer::strip_comments,
+    lua_printer::print_expr,
+    parser::{CodeBlock, ComplexToken, ComplexTokenKind, Expression, TypedName},
+};
 
-fn indent(scope: usize) -> String {
+#[derive(Debug, Clone, Default)]
+pub struct CompilerOptions {
+    pub preserve_types: bool,
+    pub compound_assignment: bool,
+    pub ternary: bool,
+    pub safe_navigation: bool,
+    pub match_statements: bool,
+    pub enums: bool,
+    pub continue_statements: bool,
+    pub default_parameters: bool,
+}
+
+// marks the start of a statement for the source map, a noncharacter is reserved for internal
+// use and shouldn't be in any source
+pub(crate) const LINE_MARK: char = '\u{FDD0}';
+
+// state shared by the compilation of a file, or of one of its top-level functions
+struct Context<'a> {
+    // every name of the file, so the locals a desugaring introduces can't capture or shadow
+    // an identifier of the file or another temporary
+    names: &'a HashSet<Arc<str>>,
+    used: RefCell<HashSet<Arc<str>>>,
+    diagnostics: RefCell<Vec<Diagnostic>>,
+    // the bodies of the top-level functions, compiled ahead of time and in order
+    bodies: RefCell<VecDeque<(String, Vec<Diagnostic>)>>,
+    // the closing values of the lowered generic for loops around the code being compiled,
+    // innermost last, a `return` skips the close after the loop so it has to close them itself
+    closing: RefCell<Vec<Arc<str>>>,
+    // only used to point diagnostics at the file
+    path: Option<&'a str>,
+    // whether statements are marked with the line they come from, for the source map
+    lines: bool,
+}
+
+impl<'a> Context<'a> {
+    fn new(names: &'a HashSet<Arc<str>>, path: Option<&'a str>, lines: bool) -> Self {
+        Self {
+            names,
+            used: RefCell::new(HashSet::new()),
+            diagnostics: RefCell::new(Vec::new()),
+            bodies: RefCell::new(VecDeque::new()),
+            closing: RefCell::new(Vec::new()),
+            path,
+            lines,
+        }
+    }
+
+    fn note(&self, diagnostic: Diagnostic) {
+        self.diagnostics.borrow_mut().push(diagnostic);
+    }
+
+    fn function_body(&self, body: &CodeBlock, scope: usize, options: &CompilerOptions) -> String {
+        if scope == 0 {
+            if let Some((code, diagnostics)) = self.bodies.borrow_mut().pop_front() {
+                self.diagnostics.borrow_mut().extend(diagnostics);
+                return code;
+            }
+        }
+        self.without_closing(|| compile_code_block(body.clone(), scope, self, options))
+    }
+
+    // a function returning doesn't leave the loops it was defined in
+    fn without_closing<T>(&self, compile: impl FnOnce() -> T) -> T {
+        let closing = self.closing.take();
+        let result = compile();
+        *self.closing.borrow_mut() = closing;
+        result
+    }
+
+    // related names share their number to stay readable
+    fn fresh<const N: usize>(&self, names: [&str; N]) -> [Arc<str>; N] {
+        let mut used = self.used.borrow_mut();
+        let fresh = (0..)
+            .map(|n| names.map(|name| Arc::from(format!("_internal_{name}_{n}"))))
+            .find(|fresh: &[Arc<str>; N]| {
+                !fresh
+                    .iter()
+                    .any(|name| self.names.contains(name) || used.contains(name))
+            })
+            .unwrap();
+        used.extend(fresh.iter().cloned());
+        fresh
+    }
+}
+
+fn indent(_scope: usize) -> String {
     // let mut result = String::new();
     // for _ in 0..scope {
     //     result += "    ";
@@ -11,7 +109,7 @@ fn indent(scope: usize) -> String {
     String::new()
 }
 
-fn indent_if<T: Iterator>(ctokens: &mut Peekable<T>, scope: usize) -> String {
+fn indent_if<T: Iterator>(_ctokens: &mut Peekable<T>, _scope: usize) -> String {
     // match ctokens.peek() {
     //     Some(_) => indent(scope),
     //     None => String::with_capacity(4),
@@ -38,6 +136,57 @@ fn compile_multiline_string(string: &str) -> String {
     String::from('`') + &string[start..end] + "`"
 }
 
+fn compile_interpolated_segment(segment: &str) -> String {
+    let mut result = String::from('"');
+    let mut chars = segment.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c @ ('`' | '{' | '}')) => result.push(c),
+                Some(c) => {
+                    result.push('\\');
+                    result.push(c);
+                }
+                None => result.push('\\'),
+            },
+            '"' => result += "\\\"",
+            '\n' => result += "\\n",
+            _ => result.push(c),
+        }
+    }
+
+    result.push('"');
+    result
+}
+
+fn compile_type(prefix: &str, ty: &Option<Arc<str>>, options: &CompilerOptions) -> String {
+    match ty {
+        Some(ty) if options.preserve_types => format!(" /* {}{} */", prefix, ty),
+        _ => String::new(),
+    }
+}
+
+fn compile_typed_name(name: &TypedName, options: &CompilerOptions) -> String {
+    name.name.to_string() + &compile_type(": ", &name.ty, options)
+}
+
+fn compile_args(
+    scope: usize,
+    args: &[(TypedName, Option<Expression>)],
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
+    compile_list(args, ",", &mut |(arg, default)| match default {
+        Some(default) => {
+            compile_typed_name(arg, options)
+                + " = "
+                + compile_expression(scope, default, context, options).trim()
+        }
+        None => compile_typed_name(arg, options),
+    })
+}
+
 fn compile_list<T, S: AsRef<str>>(
     list: &[T],
     separator: &str,
@@ -55,8 +204,15 @@ fn compile_list<T, S: AsRef<str>>(
     }
     result
 }
-fn compile_expressions(scope: usize, exprs: &[Expression]) -> String {
-    compile_list(exprs, ",", &mut |expr| compile_expression(scope, expr))
+fn compile_expressions(
+    scope: usize,
+    exprs: &[Expression],
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
+    compile_list(exprs, ",", &mut |expr| {
+        compile_expression(scope, expr, context, options)
+    })
 }
 
 fn compile_symbol(lexeme: &str) -> &str {
@@ -78,53 +234,194 @@ fn compile_operator(lexeme: &str, is_binop: bool) -> &str {
     }
 }
 
-fn compile_identifier(scope: usize, ident: &ComplexToken) -> String {
+fn compile_identifier(
+    scope: usize,
+    ident: &ComplexToken,
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
+    let crate::parser::ComplexTokenKind::Ident(expr) = ident.token() else {
+        unreachable!()
+    };
+
+    compile_optional_access(scope, expr, &[], context, options)
+}
+
+fn compile_optional_access(
+    scope: usize,
+    expr: &Expression,
+    optional: &[usize],
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
     use crate::parser::ComplexTokenKind::*;
 
     let mut result = String::new();
-    let Ident(expr) = ident.token() else {
-        unreachable!()
-    };
 
-    for ctoken in expr {
-        result += ctoken.leading();
+    for (i, ctoken) in expr.iter().enumerate() {
+        result += &strip_comments(ctoken.leading());
+        if optional.contains(&i) {
+            result.push('?');
+        }
         match ctoken.token() {
             Symbol(lexeme) => result += compile_symbol(lexeme),
             Expr(expr) => {
                 result.push('(');
-                result += &compile_expression(scope, expr);
+                result += &compile_expression(scope, expr, context, options);
                 result.push(')');
             }
             Call(args) => {
                 result.push('(');
-                result += &compile_expressions(scope, args);
+                result += &compile_expressions(scope, args, context, options);
                 result.push(')');
             }
             _ => unreachable!(),
         }
-        result += ctoken.trailing();
+        result += &strip_comments(ctoken.trailing());
+    }
+
+    result
+}
+
+fn compile_compound_alter(
+    scope: usize,
+    name: &ComplexToken,
+    operator: &str,
+    value: &Expression,
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
+    let mut result = String::new();
+    let value = compile_expression(scope, value, context, options);
+    let value = value.trim_start();
+
+    if operator == "//=" {
+        if let Some(hoisted) = compile_hoisted_floor_div(scope, name, value, context, options) {
+            return hoisted;
+        }
+    }
+
+    let name = compile_identifier(scope, name, context, options);
+    let name = name.trim_end();
+
+    result += name;
+    if operator == "//=" {
+        let trimmed = value.trim_end();
+        result += " = ";
+        result += name.trim_start();
+        result += " /_ (";
+        result += trimmed;
+        result.push(')');
+        result += &value[trimmed.len()..];
+    } else {
+        result.push(' ');
+        result += operator;
+        result.push(' ');
+        result += value;
     }
 
     result
 }
 
-fn compile_code_block(body: CodeBlock, scope: usize) -> String {
-    let code = compile_ast_helper(body.code, scope + 1);
+// Clue has no `//=`, so the target is written twice. A target whose prefix or key may have
+// side effects is evaluated once into temporaries first
+fn compile_hoisted_floor_div(
+    scope: usize,
+    name: &ComplexToken,
+    value: &str,
+    context: &Context,
+    options: &CompilerOptions,
+) -> Option<String> {
+    use ComplexTokenKind::*;
+
+    let Ident(expr) = name.token() else {
+        return None;
+    };
+    let tokens = expr.iter().map(ComplexToken::token).collect::<Vec<_>>();
+    let (len, key) = match tokens[..] {
+        [.., Symbol(dot), Symbol(_)] if dot.as_ref() == "." => (expr.len() - 2, None),
+        [.., Symbol(open), Expr(key), Symbol(close)]
+            if open.as_ref() == "[" && close.as_ref() == "]" =>
+        {
+            (expr.len() - 3, Some(key))
+        }
+        _ => return None,
+    };
+    let prefix = expr.range(..len).cloned().collect::<Expression>();
+    if idioms::is_pure(&prefix) && key.is_none_or(idioms::is_pure) {
+        return None;
+    }
+
+    let trimmed = value.trim_end();
+    let [target, temporary_key] = context.fresh(["target", "key"]);
+    let mut result = format!(
+        "{{ local {target} = {}; ",
+        compile_optional_access(scope, &prefix, &[], context, options).trim()
+    );
+    let access = match key {
+        Some(key) => {
+            result += &format!(
+                "local {temporary_key} = {}; ",
+                compile_expression(scope, key, context, options).trim()
+            );
+            format!("{target}[{temporary_key}]")
+        }
+        None => {
+            let field = compile_optional_access(
+                scope,
+                &expr.range(len..).cloned().collect(),
+                &[],
+                context,
+                options,
+            );
+            format!("{target}{}", field.trim())
+        }
+    };
+    result += &format!("{access} = {access} /_ ({trimmed}); }}");
+    result += &value[trimmed.len()..];
+
+    Some(result)
+}
+
+fn compile_code_block(
+    body: CodeBlock,
+    scope: usize,
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
+    let code = compile_ast_helper(body.code, scope + 1, context, options);
 
     code + &indent(scope)
 }
 
+// a guard chain can only be rewritten where `false` and `nil` behave the same
+fn compile_condition(
+    scope: usize,
+    condition: &Expression,
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
+    match idioms::safe_navigation(condition) {
+        Some((ident, optional)) if options.safe_navigation => {
+            compile_optional_access(scope, ident, &optional, context, options)
+        }
+        _ => compile_expression(scope, condition, context, options),
+    }
+}
+
 fn compile_if_else_chain(
     scope: usize,
     condition: &Expression,
     code: CodeBlock,
     next: Option<Box<ComplexToken>>,
+    context: &Context,
+    options: &CompilerOptions,
 ) -> String {
     use crate::parser::ComplexTokenKind::*;
     let mut result = String::new();
 
-    let condition = compile_expression(scope, condition);
-    let body = compile_code_block(code, scope);
+    let condition = compile_condition(scope, condition, context, options);
+    let body = compile_code_block(code, scope, context, options);
 
     let next = if let Some(next) = next {
         String::from(" else")
@@ -136,11 +433,11 @@ fn compile_if_else_chain(
                     ..
                 } => {
                     if condition.is_empty() {
-                        format!(" {{{}}}", compile_code_block(body, scope))
+                        format!(" {{{}}}", compile_code_block(body, scope, context, options))
                     } else {
                         format!(
                             "if {}",
-                            compile_if_else_chain(scope, &condition, body, next),
+                            compile_if_else_chain(scope, &condition, body, next, context, options),
                         )
                     }
                 }
@@ -159,13 +456,89 @@ fn compile_if_else_chain(
 
     result
 }
-fn compile_expression(mut scope: usize, expr: &Expression) -> String {
+fn compile_and_or(
+    scope: usize,
+    expr: &Expression,
+    context: &Context,
+    options: &CompilerOptions,
+) -> Option<String> {
+    let (condition, then, otherwise) = idioms::and_or(expr)?;
+    if !idioms::is_truthy(&then) {
+        context.note(
+            Diagnostic::new(
+                format!(
+                    "'{}' was not turned into a ternary because '{}' may be falsy",
+                    print_expr(expr).trim(),
+                    print_expr(&then).trim(),
+                ),
+                context.path.map(str::to_owned),
+                expr[0].position(),
+            )
+            .level(DiagnosticLevel::Note),
+        );
+        return None;
+    }
+
+    let condition = compile_expression(scope, &condition, context, options);
+    let then = compile_expression(scope, &then, context, options);
+    let otherwise = compile_expression(scope, &otherwise, context, options);
+    let (condition, then, otherwise) = (condition.as_str(), then.trim(), otherwise.as_str());
+
+    let leading = &condition[..condition.len() - condition.trim_start().len()];
+    let trailing = &otherwise[otherwise.trim_end().len()..];
+
+    Some(format!(
+        "{leading}({} ? {then} : {}){trailing}",
+        condition.trim(),
+        otherwise.trim(),
+    ))
+}
+
+fn compile_match(
+    scope: usize,
+    chain: idioms::MatchChain,
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
+    let mut result = String::from("match ");
+    result += compile_expression(scope, &chain.subject, context, options).trim();
+    result += " {";
+
+    for (value, body) in chain.arms {
+        result.push('\n');
+        result += compile_expression(scope, &value, context, options).trim();
+        result += " => {";
+        result += &compile_code_block(body.clone(), scope, context, options);
+        result.push('}');
+    }
+    if let Some(body) = chain.default {
+        result += "\ndefault => {";
+        result += &compile_code_block(body.clone(), scope, context, options);
+        result.push('}');
+    }
+
+    result += "\n}";
+    result
+}
+
+fn compile_expression(
+    mut scope: usize,
+    expr: &Expression,
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
     use crate::parser::ComplexTokenKind::*;
 
+    if options.ternary {
+        if let Some(ternary) = compile_and_or(scope, expr, context, options) {
+            return ternary;
+        }
+    }
+
     let mut result = String::new();
 
     for ctoken in expr {
-        result += ctoken.leading();
+        result += &strip_comments(ctoken.leading());
         match ctoken.token() {
             Symbol(lexeme) => result += compile_symbol(lexeme),
             Number(number) => result += &number.to_string(),
@@ -174,6 +547,44 @@ fn compile_expression(mut scope: usize, expr: &Expression) -> String {
                 result += compile_operator(op, is_binop);
             }
             MultilineString(string) => result += &compile_multiline_string(string),
+            InterpolatedString { parts, values } => {
+                let mut pieces = vec![];
+                for (i, part) in parts.iter().enumerate() {
+                    if !part.is_empty() {
+                        pieces.push(compile_interpolated_segment(part));
+                    }
+                    if let Some(value) = values.get(i) {
+                        pieces.push(format!(
+                            "tostring({})",
+                            compile_expression(scope, value, context, options).trim()
+                        ));
+                    }
+                }
+
+                match pieces.len() {
+                    0 => result += "\"\"",
+                    1 => result += &pieces[0],
+                    _ => {
+                        result.push('(');
+                        result += &pieces.join(" .. ");
+                        result.push(')');
+                    }
+                }
+            }
+            Ternary {
+                condition,
+                then,
+                otherwise,
+            } => {
+                result.push('(');
+                result += compile_expression(scope, condition, context, options).trim();
+                result += " ? ";
+                result += compile_expression(scope, then, context, options).trim();
+                result += " : ";
+                result += compile_expression(scope, otherwise, context, options).trim();
+                result.push(')');
+            }
+            TypeCast(ty) => result += &compile_type(":: ", &Some(ty.clone()), options),
             Table(data) => {
                 scope += 1;
                 result.push('{');
@@ -183,48 +594,61 @@ fn compile_expression(mut scope: usize, expr: &Expression) -> String {
                             format!(
                                 "{}{} = {}",
                                 indent(scope),
-                                compile_expression(scope, key),
-                                compile_expression(scope, value),
+                                compile_expression(scope, key, context, options),
+                                compile_expression(scope, value, context, options),
                             )
                         } else {
-                            indent(scope) + &compile_expression(scope, value)
+                            indent(scope) + &compile_expression(scope, value, context, options)
                         }
                     });
                     result += &indent(scope - 1);
                 }
                 result.push('}');
             }
-            Lambda { args, body, .. } => {
-                result += "fn ";
-                result.push('(');
-                result += &compile_list(args, ",", &mut |arg| arg.clone());
-                result += ") ";
-                result += "{";
-                result += &compile_code_block(body.clone(), scope);
+            Lambda {
+                generics,
+                args,
+                returns,
+                body,
+            } => {
+                result += "fn";
+                result += &compile_type("", generics, options);
+                result += " (";
+                result += &compile_args(scope, args, context, options);
+                result.push(')');
+                result += &compile_type(": ", returns, options);
+                result += " {";
+                result += &context
+                    .without_closing(|| compile_code_block(body.clone(), scope, context, options));
                 result.push('}');
             }
             Ident { .. } => {
-                result += &compile_identifier(scope, ctoken);
+                result += &compile_identifier(scope, ctoken, context, options);
             }
             Call(args) => {
                 result.push('(');
-                result += &compile_expressions(scope, args);
+                result += &compile_expressions(scope, args, context, options);
                 result.push(')');
             }
             Expr(expr) => {
                 result.push('(');
-                result += &compile_expression(scope, expr);
+                result += &compile_expression(scope, expr, context, options);
                 result.push(')');
             }
             _ => unreachable!(),
         }
-        result += ctoken.trailing();
+        result += &strip_comments(ctoken.trailing());
     }
 
     result
 }
 
-fn compile_ast_helper(tree: Expression, scope: usize) -> String {
+fn compile_ast_helper(
+    tree: Expression,
+    scope: usize,
+    context: &Context,
+    options: &CompilerOptions,
+) -> String {
     use crate::parser::ComplexTokenKind::*;
 
     let mut end = vec![];
@@ -232,33 +656,65 @@ fn compile_ast_helper(tree: Expression, scope: usize) -> String {
     let tree = &mut tree.into_iter().peekable();
 
     while let Some(ctoken) = tree.next() {
-        result += ctoken.leading();
+        result += &strip_comments(ctoken.leading());
+        if context.lines {
+            result.push(LINE_MARK);
+            result += &ctoken.position().line.to_string();
+            result.push(LINE_MARK);
+        }
         match ctoken.token() {
             Variable { names, values, .. } => {
                 result += "local ";
-                result += &compile_list(names, ",", &mut |(name, close)| {
-                    if *close {
-                        end.push(name.clone());
-                    }
-
-                    name.clone()
+                result += &compile_list(names, ",", &mut |(name, _)| {
+                    compile_typed_name(name, options)
                 });
                 if !values.is_empty() {
                     result += " = ";
-                    result += &compile_expressions(scope, values);
+                    result += &compile_expressions(scope, values, context, options);
+                }
+                // the variable itself may be shadowed before the block ends
+                for (name, _) in names.iter().filter(|(_, close)| *close) {
+                    let [close] = context.fresh(["close"]);
+                    result += &format!("; local {close} = {}", name.name);
+                    end.push(close);
                 }
                 result += &indent_if(tree, scope);
             }
             Alter { names, values, .. } => {
-                result += &compile_list(names, ",", &mut |name| compile_identifier(scope, name));
-                result += " = ";
-                result += &compile_expressions(scope, values);
+                let compound = match (names.as_slice(), values.as_slice()) {
+                    ([name], [value]) if options.compound_assignment => {
+                        idioms::compound_assignment(name, value)
+                            .map(|(operator, value)| (name, operator, value))
+                    }
+                    _ => None,
+                };
+
+                if let Some((name, operator, value)) = compound {
+                    result +=
+                        &compile_compound_alter(scope, name, operator, &value, context, options);
+                } else {
+                    result += &compile_list(names, ",", &mut |name| {
+                        compile_identifier(scope, name, context, options)
+                    });
+                    result += " = ";
+                    result += &compile_expressions(scope, values, context, options);
+                }
+                result += &indent_if(tree, scope);
+            }
+            CompoundAlter {
+                name,
+                operator,
+                value,
+            } => {
+                result += &compile_compound_alter(scope, name, operator, value, context, options);
                 result += &indent_if(tree, scope);
             }
             Function {
                 local,
                 name,
+                generics,
                 args,
+                returns,
                 body,
                 ..
             } => {
@@ -270,17 +726,39 @@ fn compile_ast_helper(tree: Expression, scope: usize) -> String {
                     } else {
                         result += "global ";
                     }
-                    result += "fn ";
-                } else {
-                    result += "method ";
                 }
 
-                result += &compile_expression(scope, name);
+                result += "fn ";
+                result += &compile_expression(scope, name, context, options);
+                result += &compile_type("", generics, options);
                 result.push('(');
-                result += &compile_list(args, ",", &mut |arg| arg.clone());
-                result += ") ";
-                result += "{";
-                result += &compile_code_block(body.clone(), scope);
+                result += &compile_args(scope, args, context, options);
+                result.push(')');
+                result += &compile_type(": ", returns, options);
+                result += " {";
+                result += &context.function_body(body, scope, options);
+                result.push('}');
+                result += &end;
+            }
+            Method {
+                name,
+                generics,
+                args,
+                returns,
+                body,
+            } => {
+                let end = indent_if(tree, scope);
+
+                // `self` is implicit in Clue methods too
+                result += "method ";
+                result += &compile_expression(scope, name, context, options);
+                result += &compile_type("", generics, options);
+                result.push('(');
+                result += &compile_args(scope, &args[1..], context, options);
+                result.push(')');
+                result += &compile_type(": ", returns, options);
+                result += " {";
+                result += &context.function_body(body, scope, options);
                 result.push('}');
                 result += &end;
             }
@@ -290,15 +768,34 @@ fn compile_ast_helper(tree: Expression, scope: usize) -> String {
                 next,
                 ..
             } => {
-                let code = compile_if_else_chain(scope, condition, body.clone(), next.clone());
-                result += &code;
+                let guarded = idioms::guarded_call(condition, body)
+                    .filter(|_| options.safe_navigation && next.is_none());
+                let chain =
+                    idioms::match_chain(condition, body, next).filter(|_| options.match_statements);
+
+                if let Some((call, optional)) = guarded {
+                    result +=
+                        compile_optional_access(scope, call, &optional, context, options).trim();
+                    result.push(';');
+                } else if let Some(chain) = chain {
+                    result += &compile_match(scope, chain, context, options);
+                } else {
+                    result += &compile_if_else_chain(
+                        scope,
+                        condition,
+                        body.clone(),
+                        next.clone(),
+                        context,
+                        options,
+                    );
+                }
                 result += &indent_if(tree, scope);
             }
             WhileLoop {
                 condition, body, ..
             } => {
-                let condition = compile_expression(scope, condition);
-                let body = compile_code_block(body.clone(), scope);
+                let condition = compile_condition(scope, condition, context, options);
+                let body = compile_code_block(body.clone(), scope, context, options);
 
                 result += "while ";
                 result += &condition;
@@ -316,17 +813,17 @@ fn compile_ast_helper(tree: Expression, scope: usize) -> String {
                 ..
             } => {
                 result += "for ";
-                result += iter;
+                result += &compile_typed_name(iter, options);
                 result += " = ";
-                result += &compile_expression(scope, start);
+                result += &compile_expression(scope, start, context, options);
                 result += ",";
-                result += &compile_expression(scope, end);
+                result += &compile_expression(scope, end, context, options);
                 if let Some(step) = step {
                     result += ",";
-                    result += &compile_expression(scope, step);
+                    result += &compile_expression(scope, step, context, options);
                 }
                 result += " {";
-                result += &compile_code_block(code.clone(), scope);
+                result += &compile_code_block(code.clone(), scope, context, options);
                 result.push('}');
                 result += &indent_if(tree, scope);
             }
@@ -335,61 +832,51 @@ fn compile_ast_helper(tree: Expression, scope: usize) -> String {
                 expr,
                 stop,
                 initial,
+                closing,
                 code,
                 ..
             } => {
-                if stop.is_some() || initial.is_some() {
-                    let s = scope;
-                    let iters_compiled = &compile_list(iters, ",", &mut |iter| iter.clone());
+                if stop.is_some() || initial.is_some() || closing.is_some() {
+                    let [function, state, control, closing_value] =
+                        context.fresh(["expr", "stop", "acc", "close"]);
+                    let values: Vec<Expression> = [
+                        Some(expr),
+                        stop.as_ref(),
+                        initial.as_ref(),
+                        closing.as_ref(),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    .cloned()
+                    .collect();
 
+                    // the values are adjusted like an explist, so a call may fill the rest
                     result += "{\n";
-                    let scope = scope + 1;
-                    result += &indent(scope);
-                    result += &format!(
-                        "local _internal_expr_{0}, _internal_stop_{0}, _internal_acc_{0} = ",
-                        s
-                    );
-                    result += &compile_expression(scope, expr);
-                    result += ",";
-                    result += &compile_expression(scope, stop.as_ref().unwrap());
-                    result += ",";
-                    result += &initial.as_ref().map_or("nil".to_owned(), |initial| {
-                        compile_expression(scope, initial)
-                    });
+                    result += &format!("local {function}, {state}, {control}, {closing_value} = ");
+                    result += &compile_expressions(scope + 1, &values, context, options);
                     result += ";\n";
-                    result += &indent(scope);
                     result += "while true {\n";
-                    let scope = scope + 1;
-                    result += &indent(scope);
                     result += "local ";
-                    result += iters_compiled;
-                    result += " = ";
-                    result += &format!(
-                        "_internal_expr_{0}(_internal_stop_{0}, _internal_acc_{0});\n",
-                        s
-                    );
-                    result += &indent(scope);
-                    result += &format!("_internal_acc_{s} = ");
-                    result += &iters[0];
-                    result += ";\n";
-                    result += &indent(scope);
-                    result += "if ";
-                    result += &format!("_internal_acc_{s}");
-                    result += " == nil";
-                    result += " { break; }";
-
-                    let scope = scope - 1;
-                    result += &compile_code_block(code.clone(), scope);
+                    result +=
+                        &compile_list(iters, ",", &mut |iter| compile_typed_name(iter, options));
+                    result += &format!(" = {function}({state}, {control});\n");
+                    result += &format!("if {} == nil {{ break; }}\n", iters[0].name);
+                    result += &format!("{control} = {};", iters[0].name);
+                    context.closing.borrow_mut().push(closing_value.clone());
+                    result += &compile_code_block(code.clone(), scope + 1, context, options);
+                    context.closing.borrow_mut().pop();
                     result += "}\n";
-                    result += &indent(scope - 1);
+                    result += &close_value(&closing_value);
+                    result += "\n";
                     result += "}\n";
                 } else {
                     result += "for ";
-                    result += &compile_list(iters, ",", &mut |iter| iter.clone());
+                    result +=
+                        &compile_list(iters, ",", &mut |iter| compile_typed_name(iter, options));
                     result += " with ";
-                    result += &compile_expression(scope, expr);
+                    result += &compile_expression(scope, expr, context, options);
                     result += " {";
-                    result += &compile_code_block(code.clone(), scope);
+                    result += &compile_code_block(code.clone(), scope, context, options);
                     result.push('}');
                     result += &indent_if(tree, scope);
                 }
@@ -397,8 +884,8 @@ fn compile_ast_helper(tree: Expression, scope: usize) -> String {
             RepeatLoop {
                 condition, body, ..
             } => {
-                let condition = compile_expression(scope, condition);
-                let body = compile_code_block(body.clone(), scope);
+                let condition = compile_condition(scope, condition, context, options);
+                let body = compile_code_block(body.clone(), scope, context, options);
 
                 result += "loop ";
                 result += " {";
@@ -409,42 +896,92 @@ fn compile_ast_helper(tree: Expression, scope: usize) -> String {
                 result += &indent_if(tree, scope);
             }
             Ident { .. } => {
-                result += &compile_identifier(scope, &ctoken);
+                result += &compile_identifier(scope, &ctoken, context, options);
                 result.push(';');
                 result += &indent_if(tree, scope);
             }
             Call(args) => {
                 result.push('(');
-                result += &compile_expressions(scope, args);
+                result += &compile_expressions(scope, args, context, options);
                 result.push(')');
             }
             Expr(expr) => {
                 result.push('(');
-                result += &compile_expression(scope, expr);
+                result += &compile_expression(scope, expr, context, options);
                 result.push(')');
             }
             DoBlock(body) => {
                 result += "{";
-                result += &compile_code_block(body.clone(), scope);
+                result += &compile_code_block(body.clone(), scope, context, options);
                 result += "}";
                 result += &indent_if(tree, scope);
             }
             Return(exprs) => {
-                result += "return";
-                if let Some(exprs) = exprs {
-                    result.push(' ');
-                    result +=
-                        &compile_list(exprs, ",", &mut |expr| compile_expression(scope, expr));
+                let close = context
+                    .closing
+                    .borrow()
+                    .iter()
+                    .rev()
+                    .map(|value| close_value(value))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                match exprs {
+                    Some(exprs) => {
+                        let exprs = compile_list(exprs, ",", &mut |expr| {
+                            compile_expression(scope, expr, context, options)
+                        });
+                        if close.is_empty() {
+                            result += &format!("return {exprs}");
+                        } else {
+                            // the values are computed before anything is closed, the function
+                            // keeps how many there are
+                            result +=
+                                &format!("return (fn (...) {{ {close} return ...; }})({exprs})");
+                        }
+                    }
+                    None if close.is_empty() => result += "return",
+                    None => result += &format!("{close} return"),
                 }
             }
             Break => {
                 result += "break";
                 result += &indent_if(tree, scope);
             }
+            Continue => {
+                result += "continue";
+                result += &indent_if(tree, scope);
+            }
+            TypeAlias {
+                export,
+                name,
+                generics,
+                value,
+            } => {
+                if options.preserve_types {
+                    result += "/* ";
+                    if *export {
+                        result += "export ";
+                    }
+                    result += "type ";
+                    result += name;
+                    if let Some(generics) = generics {
+                        result += generics;
+                    }
+                    result += " = ";
+                    result += value;
+                    result += " */";
+                }
+                result += &indent_if(tree, scope);
+            }
+            Enum(table) => {
+                result += "enum ";
+                result += compile_expression(scope, table, context, options).trim_start();
+                result += &indent_if(tree, scope);
+            }
 
             _ => unreachable!(),
         }
-        result += ctoken.trailing();
+        result += &strip_comments(ctoken.trailing());
     }
 
     for name in end.iter().rev() {
@@ -460,6 +997,108 @@ fn compile_ast_helper(tree: Expression, scope: usize) -> String {
     result
 }
 
-pub fn compile_ast(tree: Expression) -> String {
-    compile_ast_helper(tree, 0)
+// Lua 5.4 ignores closing values that are `nil` or `false`
+fn close_value(value: &str) -> String {
+    format!("if {value} {{ getmetatable({value}).__close({value}); }}")
+}
+
+// the temporaries of a function can't be seen from outside, so each body gets its own context
+// and they can all be compiled at the same time
+fn compile_bodies(
+    tree: &Expression,
+    names: &HashSet<Arc<str>>,
+    path: Option<&str>,
+    lines: bool,
+    options: &CompilerOptions,
+) -> VecDeque<(String, Vec<Diagnostic>)> {
+    let compile = |ctoken: &ComplexToken| match ctoken.token() {
+        ComplexTokenKind::Function { body, .. } | ComplexTokenKind::Method { body, .. } => {
+            let context = Context::new(names, path, lines);
+            let code = compile_code_block(body.clone(), 0, &context, options);
+            Some((code, context.diagnostics.into_inner()))
+        }
+        _ => None,
+    };
+
+    #[cfg(feature = "parallel")]
+    return tree.par_iter().filter_map(compile).collect();
+    #[cfg(not(feature = "parallel"))]
+    tree.iter().filter_map(compile).collect()
+}
+
+pub fn compile_ast(
+    tree: Expression,
+    path: Option<String>,
+    options: &CompilerOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> String {
+    compile(tree, path, false, options, diagnostics)
+}
+
+// the source line of every line of the code, the ones that don't start a statement belong to
+// the last statement started before them
+pub(crate) fn compile_ast_lines(
+    tree: Expression,
+    path: Option<String>,
+    options: &CompilerOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> (String, Vec<usize>) {
+    let marked = compile(tree, path, true, options, diagnostics);
+    let mut code = String::with_capacity(marked.len());
+    let mut lines = Vec::new();
+    let mut last = 1;
+    let mut line = None;
+    let mut pending = None;
+
+    let mut parts = marked.split(LINE_MARK);
+    while let Some(part) = parts.next() {
+        for c in part.chars() {
+            if c == '\n' {
+                lines.push(line.unwrap_or(last));
+                line = None;
+            } else if !c.is_whitespace() {
+                // a statement starts at its first character, not at the indentation before it
+                if let Some(pending) = pending.take() {
+                    last = pending;
+                    line.get_or_insert(pending);
+                }
+            }
+            code.push(c);
+        }
+        pending = parts.next().and_then(|number| number.parse().ok());
+    }
+    lines.push(line.unwrap_or(last));
+
+    (code, lines)
+}
+
+fn compile(
+    mut tree: Expression,
+    path: Option<String>,
+    lines: bool,
+    options: &CompilerOptions,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> String {
+    if options.enums {
+        idioms::enums(&mut tree, path.clone(), diagnostics);
+    }
+    if options.continue_statements {
+        idioms::continues(&mut tree);
+    }
+    if options.default_parameters {
+        idioms::default_parameters(&mut tree);
+    }
+    let names = idioms::names(&mut tree);
+    let context = Context::new(&names, path.as_deref(), lines);
+    context.bodies.replace(compile_bodies(
+        &tree,
+        &names,
+        path.as_deref(),
+        lines,
+        options,
+    ));
+
+    let compiled = compile_ast_helper(tree, 0, &context, options);
+    diagnostics.append(&mut context.diagnostics.into_inner());
+    compiled
 }
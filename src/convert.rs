@@ -0,0 +1,65 @@
+use crate::{
+    compiler::{compile_ast, compile_ast_lines, CompilerOptions, LINE_MARK},
+    error::Diagnostic,
+    lexer::{scan_code, Dialect},
+    parser::parse_tokens,
+};
+
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    // only used to point diagnostics at the file
+    pub path: Option<String>,
+    pub dialect: Dialect,
+    pub compiler: CompilerOptions,
+    pub source_map: bool,
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct ConvertOutput {
+    // `None` when the source could not be converted, the last diagnostic says why
+    pub code: Option<String>,
+    pub diagnostics: Vec<Diagnostic>,
+    // the source line of every line of the code, when it was asked for and the code exists
+    pub source_map: Option<Vec<usize>>,
+}
+
+pub fn convert(source: &str, options: &ConvertOptions) -> ConvertOutput {
+    let mut diagnostics = Vec::new();
+    let compiled = scan_code(
+        source,
+        options.path.clone(),
+        options.dialect,
+        &mut diagnostics,
+    )
+    .and_then(|scanned| parse_tokens(&scanned, options.path.clone(), &mut diagnostics))
+    .map(|parsed| {
+        let path = options.path.clone();
+        if options.source_map && !source.contains(LINE_MARK) {
+            let (code, lines) =
+                compile_ast_lines(parsed, path, &options.compiler, &mut diagnostics);
+            (code, Some(lines))
+        } else {
+            (
+                compile_ast(parsed, path, &options.compiler, &mut diagnostics),
+                None,
+            )
+        }
+    });
+
+    match compiled {
+        Ok((code, source_map)) => ConvertOutput {
+            code: Some(code),
+            diagnostics,
+            source_map,
+        },
+        Err(error) => {
+            diagnostics.push(error);
+            ConvertOutput {
+                code: None,
+                diagnostics,
+                source_map: None,
+            }
+        }
+    }
+}
@@ -1,12 +1,9 @@
 use std::{
     collections::HashMap,
     error::Error,
-    fmt,
-    sync::{Arc, Mutex, OnceLock},
+    fmt::{self, Write},
 };
 
-use colored::Colorize;
-
 use crate::lexer::Position;
 
 #[derive(Debug)]
@@ -26,12 +23,24 @@ enum DiagnosticKind {
     Other,
 }
 
-type Files = Arc<Mutex<HashMap<String, String>>>;
-static FILES: OnceLock<Files> = OnceLock::new();
-pub fn get_files() -> Files {
-    FILES
-        .get_or_init(|| Arc::new(Mutex::new(HashMap::new())))
-        .clone()
+// sources that diagnostics can quote, owned by whoever renders them
+#[derive(Debug, Clone, Default)]
+pub struct SourceDb {
+    files: HashMap<String, String>,
+}
+
+impl SourceDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&mut self, path: String, source: String) {
+        self.files.insert(path, source);
+    }
+
+    pub fn get(&self, path: &str) -> Option<&str> {
+        self.files.get(path).map(String::as_str)
+    }
 }
 
 #[derive(Debug, Default)]
@@ -130,60 +139,115 @@ impl Diagnostic {
         self.hint = Some(hint);
         self
     }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn hint(&self) -> Option<&str> {
+        self.hint.as_deref()
+    }
+
+    pub const fn severity(&self) -> &DiagnosticLevel {
+        &self.level
+    }
+
+    pub fn path(&self) -> Option<&str> {
+        match &self.kind {
+            DiagnosticKind::Compiler { path, .. } => path.as_deref(),
+            DiagnosticKind::Other => None,
+        }
+    }
+
+    pub const fn position(&self) -> Option<&Position> {
+        match &self.kind {
+            DiagnosticKind::Compiler { position, .. } => Some(position),
+            DiagnosticKind::Other => None,
+        }
+    }
 }
 
 impl Error for Diagnostic {}
 
-impl fmt::Display for Diagnostic {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match &self.level {
-            DiagnosticLevel::Error => write!(f, "{}", "error: ".red().bold())?,
-            DiagnosticLevel::Warning => write!(f, "{}", "warning: ".yellow().bold())?,
-            DiagnosticLevel::Note => write!(f, "{}", "note: ".blue().bold())?,
-        }
+#[derive(Clone, Copy)]
+enum Style {
+    Red,
+    Yellow,
+    Blue,
+}
+
+#[cfg_attr(not(feature = "color"), allow(unused_variables))]
+fn paint(text: &str, style: Style, color: bool) -> String {
+    #[cfg(feature = "color")]
+    if color {
+        use colored::Colorize;
+
+        let text = match style {
+            Style::Red => text.red(),
+            Style::Yellow => text.yellow(),
+            Style::Blue => text.blue(),
+        };
+        return text.bold().to_string();
+    }
+    text.to_owned()
+}
+
+impl Diagnostic {
+    // quotes the offending lines when `sources` knows the file, with colors if they are enabled
+    pub fn render(&self, sources: &SourceDb) -> String {
+        let mut result = String::new();
+        self.write(&mut result, sources, true).unwrap();
+        result
+    }
+
+    pub fn render_plain(&self, sources: &SourceDb) -> String {
+        let mut result = String::new();
+        self.write(&mut result, sources, false).unwrap();
+        result
+    }
+
+    fn write(&self, f: &mut impl Write, sources: &SourceDb, color: bool) -> fmt::Result {
+        let style = match self.level {
+            DiagnosticLevel::Error => Style::Red,
+            DiagnosticLevel::Warning => Style::Yellow,
+            DiagnosticLevel::Note => Style::Blue,
+        };
+        let label = match self.level {
+            DiagnosticLevel::Error => "error: ",
+            DiagnosticLevel::Warning => "warning: ",
+            DiagnosticLevel::Note => "note: ",
+        };
+        write!(f, "{}", paint(label, style, color))?;
 
         write!(f, "{}", self.message)?;
         match &self.kind {
             DiagnosticKind::Compiler { path, position } => {
                 let Position { line, column, .. } = &position;
-                write!(f, "\n  {} ", "-->".blue().bold())?;
+                write!(f, "\n  {} ", paint("-->", Style::Blue, color))?;
                 if let Some(path) = path {
                     write!(f, "{}:", path)?;
                 }
                 writeln!(f, "{}:{}", line, column)?;
 
-                if let Some(path) = path {
-                    let files = get_files();
-                    let files = files.lock().unwrap();
-                    let source = files.get(path);
-
-                    if let Some(source) = source {
-                        let mut start = position.span.start;
-                        while start > 0 && source.chars().nth(start - 1) != Some('\n') {
-                            start -= 1;
-                        }
-                        let mut end = position.span.end;
-                        while end < source.len() && source.chars().nth(end) != Some('\n') {
-                            end += 1;
-                        }
+                if let Some(source) = path.as_deref().and_then(|path| sources.get(path)) {
+                    let start = source[..position.span.start]
+                        .rfind('\n')
+                        .map_or(0, |i| i + 1);
+                    let end = source[position.span.end..]
+                        .find('\n')
+                        .map_or(source.len(), |i| position.span.end + i);
+
+                    let source = &source[start..end];
+                    let end = source[..position.span.end - start].chars().count();
+                    let start = source[..position.span.start - start].chars().count();
+                    let pointer = paint(&"^".repeat(end - start), style, color);
 
-                        let source = source[start..end].to_owned();
-                        let end = position.span.end - start;
-                        let start = position.span.start - start;
-                        let pointer = "^".repeat(end - start);
-                        let pointer = match self.level {
-                            DiagnosticLevel::Error => pointer.red().bold(),
-                            DiagnosticLevel::Warning => pointer.yellow().bold(),
-                            DiagnosticLevel::Note => pointer.blue().bold(),
-                        };
-
-                        for (i, line) in source.lines().enumerate() {
-                            if i > 0 {
-                                writeln!(f)?;
-                            }
-                            writeln!(f, "{:4} | {}", i + position.line, line)?;
-                            write!(f, "     | {}{}", " ".repeat(start), pointer)?;
+                    for (i, line) in source.lines().enumerate() {
+                        if i > 0 {
+                            writeln!(f)?;
                         }
+                        writeln!(f, "{:4} | {}", i + position.line, line)?;
+                        write!(f, "     | {}{}", " ".repeat(start), pointer)?;
                     }
                 }
             }
@@ -198,3 +262,9 @@ impl fmt::Display for Diagnostic {
         Ok(())
     }
 }
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.write(f, &SourceDb::default(), true)
+    }
+}
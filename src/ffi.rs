@@ -0,0 +1,263 @@
+use std::{
+    ffi::{c_char, CStr},
+    panic::{catch_unwind, AssertUnwindSafe},
+    ptr, slice,
+};
+
+use crate::{
+    compiler::CompilerOptions,
+    error::{Diagnostic, DiagnosticLevel, SourceDb},
+    lexer::Dialect,
+    ConvertOptions, ConvertOutput,
+};
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum ClunaDialect {
+    Lua,
+    Luau,
+    Glua,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub enum ClunaLevel {
+    Error,
+    Warning,
+    Note,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct ClunaOptions {
+    pub dialect: ClunaDialect,
+    /// nul terminated, may be null, only used to point diagnostics at the file
+    pub path: *const c_char,
+    pub preserve_types: bool,
+    pub compound_assignment: bool,
+    pub ternary: bool,
+    pub safe_navigation: bool,
+    pub match_statements: bool,
+    pub enums: bool,
+    pub continue_statements: bool,
+    pub default_parameters: bool,
+}
+
+/// every string is nul terminated, the optional ones are null when missing
+/// line and column start from 1, all the position fields are 0 when the diagnostic has no position
+#[repr(C)]
+#[derive(Debug)]
+pub struct ClunaDiagnostic {
+    pub level: ClunaLevel,
+    pub message: *mut c_char,
+    pub hint: *mut c_char,
+    pub path: *mut c_char,
+    pub line: usize,
+    pub column: usize,
+    pub start: usize,
+    pub end: usize,
+    /// the diagnostic as the cli would print it, without colors
+    pub rendered: *mut c_char,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+pub struct ClunaResult {
+    /// null when the source could not be converted, the last diagnostic says why
+    /// nul terminated, code_len doesn't count the terminator
+    pub code: *mut c_char,
+    pub code_len: usize,
+    pub diagnostics: *mut ClunaDiagnostic,
+    pub diagnostics_len: usize,
+}
+
+fn owned_bytes(mut bytes: Vec<u8>) -> *mut c_char {
+    bytes.push(0);
+    Box::into_raw(bytes.into_boxed_slice()).cast()
+}
+
+// diagnostics can quote nul bytes of the source, they can't be part of a C string
+fn owned_string(text: &str) -> *mut c_char {
+    owned_bytes(text.replace('\0', "\\0").into_bytes())
+}
+
+unsafe fn free_bytes(bytes: *mut c_char, len: usize) {
+    if !bytes.is_null() {
+        drop(Box::from_raw(ptr::slice_from_raw_parts_mut(
+            bytes.cast::<u8>(),
+            len + 1,
+        )));
+    }
+}
+
+unsafe fn free_string(text: *mut c_char) {
+    if !text.is_null() {
+        let len = CStr::from_ptr(text).to_bytes().len();
+        free_bytes(text, len);
+    }
+}
+
+fn diagnostic(diagnostic: &Diagnostic, sources: &SourceDb) -> ClunaDiagnostic {
+    let level = match diagnostic.severity() {
+        DiagnosticLevel::Error => ClunaLevel::Error,
+        DiagnosticLevel::Warning => ClunaLevel::Warning,
+        DiagnosticLevel::Note => ClunaLevel::Note,
+    };
+    let (line, column, start, end) = match diagnostic.position() {
+        Some(position) => (
+            position.line,
+            position.column,
+            position.span.start,
+            position.span.end,
+        ),
+        None => (0, 0, 0, 0),
+    };
+    ClunaDiagnostic {
+        level,
+        message: owned_string(diagnostic.message()),
+        hint: diagnostic.hint().map_or(ptr::null_mut(), owned_string),
+        path: diagnostic.path().map_or(ptr::null_mut(), owned_string),
+        line,
+        column,
+        start,
+        end,
+        rendered: owned_string(&diagnostic.render_plain(sources)),
+    }
+}
+
+unsafe fn convert_options(options: &ClunaOptions) -> Result<ConvertOptions, Diagnostic> {
+    let path = if options.path.is_null() {
+        None
+    } else {
+        match CStr::from_ptr(options.path).to_str() {
+            Ok(path) => Some(path.to_owned()),
+            Err(_) => return Err(Diagnostic::other(String::from("Path is not valid UTF-8"))),
+        }
+    };
+    let dialect = match options.dialect {
+        ClunaDialect::Lua => Dialect::Lua,
+        ClunaDialect::Luau => Dialect::Luau,
+        ClunaDialect::Glua => Dialect::GLua,
+    };
+    Ok(ConvertOptions {
+        path,
+        dialect,
+        compiler: CompilerOptions {
+            preserve_types: options.preserve_types,
+            compound_assignment: options.compound_assignment,
+            ternary: options.ternary,
+            safe_navigation: options.safe_navigation,
+            match_statements: options.match_statements,
+            enums: options.enums,
+            continue_statements: options.continue_statements,
+            default_parameters: options.default_parameters,
+        },
+        ..Default::default()
+    })
+}
+
+unsafe fn convert(src: *const c_char, len: usize, options: *const ClunaOptions) -> ClunaResult {
+    let options = match options.as_ref().map(|options| convert_options(options)) {
+        Some(Ok(options)) => options,
+        Some(Err(error)) => return result(None, &[error], &SourceDb::new()),
+        None => ConvertOptions::default(),
+    };
+    let source = if len == 0 {
+        &[]
+    } else {
+        slice::from_raw_parts(src.cast::<u8>(), len)
+    };
+    let Ok(source) = std::str::from_utf8(source) else {
+        let error = Diagnostic::other(String::from("Source is not valid UTF-8"));
+        return result(None, &[error], &SourceDb::new());
+    };
+
+    // a panic can't unwind into the caller
+    let output = catch_unwind(AssertUnwindSafe(|| crate::convert(source, &options)))
+        .unwrap_or_else(|_| ConvertOutput {
+            code: None,
+            diagnostics: vec![Diagnostic::other(String::from(
+                "Internal error while converting the source",
+            ))],
+            source_map: None,
+        });
+    let mut sources = SourceDb::new();
+    if let Some(path) = options.path {
+        sources.insert(path, source.to_owned());
+    }
+    result(output.code, &output.diagnostics, &sources)
+}
+
+fn result(code: Option<String>, diagnostics: &[Diagnostic], sources: &SourceDb) -> ClunaResult {
+    let (code, code_len) = match code {
+        Some(code) => {
+            let len = code.len();
+            (owned_bytes(code.into_bytes()), len)
+        }
+        None => (ptr::null_mut(), 0),
+    };
+    let diagnostics: Box<[ClunaDiagnostic]> = diagnostics
+        .iter()
+        .map(|error| diagnostic(error, sources))
+        .collect();
+    ClunaResult {
+        code,
+        code_len,
+        diagnostics_len: diagnostics.len(),
+        diagnostics: Box::into_raw(diagnostics).cast(),
+    }
+}
+
+/// Returns the options `cluna_convert` uses when it is given null options.
+#[no_mangle]
+pub extern "C" fn cluna_options_default() -> ClunaOptions {
+    ClunaOptions {
+        dialect: ClunaDialect::Lua,
+        path: ptr::null(),
+        preserve_types: false,
+        compound_assignment: false,
+        ternary: false,
+        safe_navigation: false,
+        match_statements: false,
+        enums: false,
+        continue_statements: false,
+        default_parameters: false,
+    }
+}
+
+/// Converts `len` bytes of UTF-8 Lua source at `src`, with the default options when `options` is null.
+///
+/// # Safety
+/// `src` must point to `len` readable bytes and `options` must be null or point to valid options.
+/// The result is never null and must be released with `cluna_result_free`.
+#[no_mangle]
+pub unsafe extern "C" fn cluna_convert(
+    src: *const c_char,
+    len: usize,
+    options: *const ClunaOptions,
+) -> *mut ClunaResult {
+    Box::into_raw(Box::new(convert(src, len, options)))
+}
+
+/// Releases a result and everything it owns, does nothing when `result` is null.
+///
+/// # Safety
+/// `result` must be null or come from `cluna_convert`, and must not be used after this call.
+#[no_mangle]
+pub unsafe extern "C" fn cluna_result_free(result: *mut ClunaResult) {
+    if result.is_null() {
+        return;
+    }
+    let result = Box::from_raw(result);
+    free_bytes(result.code, result.code_len);
+    let diagnostics = Box::from_raw(ptr::slice_from_raw_parts_mut(
+        result.diagnostics,
+        result.diagnostics_len,
+    ));
+    for diagnostic in diagnostics.iter() {
+        free_string(diagnostic.message);
+        free_string(diagnostic.hint);
+        free_string(diagnostic.path);
+        free_string(diagnostic.rendered);
+    }
+}
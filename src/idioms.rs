@@ -0,0 +1,638 @@
+use crate::{
+    error::{Diagnostic, DiagnosticLevel},
+    parser::{
+        walk_token_mut, CodeBlock, ComplexToken, ComplexTokenKind, Expression, FunctionArgs,
+        VisitorMut,
+    },
+};
+use std::{collections::HashSet, sync::Arc};
+
+fn precedence(operator: &str) -> u8 {
+    match operator {
+        "or" | "||" => 1,
+        "and" | "&&" => 2,
+        "<" | ">" | "<=" | ">=" | "~=" | "!=" | "==" => 3,
+        "|" => 4,
+        "~" => 5,
+        "&" => 6,
+        "<<" | ">>" => 7,
+        ".." => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "//" | "%" => 10,
+        "^" => 12,
+        _ => 0,
+    }
+}
+
+const fn is_right_associative(operator: &str) -> bool {
+    matches!(operator.as_bytes(), b".." | b"^")
+}
+
+// only names, literals and operators, nothing that may call into user code
+pub(crate) fn is_pure(expr: &Expression) -> bool {
+    use ComplexTokenKind::*;
+
+    expr.iter().all(|ctoken| match ctoken.token() {
+        Symbol(_) | Number(_) | MultilineString(_) | Operator(_) => true,
+        Ident(expr) | Expr(expr) => is_pure(expr),
+        _ => false,
+    })
+}
+
+// compares two expressions while ignoring whitespace
+fn same_expression(a: &Expression, b: &Expression) -> bool {
+    use ComplexTokenKind::*;
+
+    a.len() == b.len()
+        && a.iter().zip(b).all(|(a, b)| match (a.token(), b.token()) {
+            (Ident(a), Ident(b)) | (Expr(a), Expr(b)) => same_expression(a, b),
+            (a @ (Symbol(_) | Number(_) | MultilineString(_) | Operator(_)), b) => a == b,
+            _ => false,
+        })
+}
+
+// `x = x + y` can become `x += y` if `y` binds tighter than `+` and `x` has no side effects
+pub(crate) fn compound_assignment(
+    name: &ComplexToken,
+    value: &Expression,
+) -> Option<(&'static str, Expression)> {
+    use ComplexTokenKind::*;
+
+    let Ident(target) = name.token() else {
+        return None;
+    };
+    let (Some(Ident(first)), Some(Operator((operator, true)))) = (
+        value.front().map(ComplexToken::token),
+        value.get(1).map(ComplexToken::token),
+    ) else {
+        return None;
+    };
+    if !is_pure(target) || !same_expression(target, first) || value.len() < 3 {
+        return None;
+    }
+
+    let compound = match operator.as_ref() {
+        "+" => "+=",
+        "-" => "-=",
+        "*" => "*=",
+        "/" => "/=",
+        "%" => "%=",
+        "^" => "^=",
+        ".." => "..=",
+        _ => return None,
+    };
+
+    let rest = value.range(2..).cloned().collect::<Expression>();
+    let binds_tighter = rest.iter().all(|ctoken| match ctoken.token() {
+        Operator((op, true)) => {
+            precedence(op) > precedence(operator)
+                || (op == operator && is_right_associative(operator))
+        }
+        _ => true,
+    });
+
+    binds_tighter.then_some((compound, rest))
+}
+
+fn split_at_operator(expr: &Expression, operators: &[&str]) -> Option<(Expression, Expression)> {
+    let i = expr.iter().rposition(|ctoken| {
+        matches!(ctoken.token(), ComplexTokenKind::Operator((op, true)) if operators.contains(&op.as_ref()))
+    })?;
+    let left = expr.range(..i).cloned().collect();
+    let right = expr.range(i + 1..).cloned().collect();
+
+    Some((left, right))
+}
+
+// splits `cond and a or b` into its three parts, there must be exactly one top level `or`
+pub(crate) fn and_or(expr: &Expression) -> Option<(Expression, Expression, Expression)> {
+    const OR: &[&str] = &["or", "||"];
+
+    let (left, otherwise) = split_at_operator(expr, OR)?;
+    if split_at_operator(&left, OR).is_some() {
+        return None;
+    }
+    let (condition, then) = split_at_operator(&left, &["and", "&&"])?;
+
+    (!condition.is_empty() && !then.is_empty() && !otherwise.is_empty())
+        .then_some((condition, then, otherwise))
+}
+
+// literals, tables and functions can never be `nil` or `false`
+pub(crate) fn is_truthy(expr: &Expression) -> bool {
+    use ComplexTokenKind::*;
+
+    // a parenthesized expression is followed by an empty identifier
+    let tokens = expr
+        .iter()
+        .filter(|ctoken| !matches!(ctoken.token(), Ident(expr) if expr.is_empty()))
+        .collect::<Vec<_>>();
+    let [ctoken] = tokens[..] else {
+        return false;
+    };
+    match ctoken.token() {
+        Symbol(symbol) => symbol.as_ref() == "true" || symbol.starts_with(['"', '\'']),
+        Number(_) | MultilineString(_) | InterpolatedString { .. } | Table(_) | Lambda { .. } => {
+            true
+        }
+        Ident(expr) => is_truthy(expr),
+        Expr(expr) => match and_or(expr) {
+            Some((_, then, otherwise)) => is_truthy(&then) && is_truthy(&otherwise),
+            None => is_truthy(expr),
+        },
+        _ => false,
+    }
+}
+
+// `a and a.b and a.b:c()` as the identifiers being guarded, each one extending the previous
+fn guard_chain(expr: &Expression) -> Option<Vec<&Expression>> {
+    use ComplexTokenKind::*;
+
+    let mut chain: Vec<&Expression> = Vec::new();
+    for (i, ctoken) in expr.iter().enumerate() {
+        match ctoken.token() {
+            Ident(ident) if i % 2 == 0 => {
+                if let Some(&previous) = chain.last() {
+                    if !extends(ident, previous) {
+                        return None;
+                    }
+                }
+                chain.push(ident);
+            }
+            Operator((op, true)) if i % 2 == 1 && matches!(op.as_ref(), "and" | "&&") => {}
+            _ => return None,
+        }
+    }
+
+    (expr.len() % 2 == 1).then_some(chain)
+}
+
+// `previous` must be free of side effects, it is evaluated only once after the rewrite
+fn extends(ident: &Expression, previous: &Expression) -> bool {
+    use ComplexTokenKind::*;
+
+    ident.len() > previous.len()
+        && is_pure(previous)
+        && same_expression(&ident.range(..previous.len()).cloned().collect(), previous)
+        && matches!(ident[previous.len()].token(), Symbol(s) if matches!(s.as_ref(), "." | ":"))
+}
+
+// the identifier to print and the indices of the accesses that become optional
+pub(crate) fn safe_navigation(expr: &Expression) -> Option<(&Expression, Vec<usize>)> {
+    let chain = guard_chain(expr)?;
+    let (last, guards) = chain.split_last()?;
+
+    (!guards.is_empty()).then(|| (*last, guards.iter().map(|guard| guard.len()).collect()))
+}
+
+// `if a and a.b then a.b:close() end` calls `a?.b?::close()`
+pub(crate) fn guarded_call<'a>(
+    condition: &'a Expression,
+    body: &'a CodeBlock,
+) -> Option<(&'a Expression, Vec<usize>)> {
+    let chain = guard_chain(condition)?;
+    let [statement] = body.code.iter().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    let ComplexTokenKind::Ident(call) = statement.token() else {
+        return None;
+    };
+    if !extends(call, chain.last()?) {
+        return None;
+    }
+
+    Some((call, chain.iter().map(|guard| guard.len()).collect()))
+}
+
+fn constant(expr: &Expression) -> Option<String> {
+    use ComplexTokenKind::*;
+
+    match expr.iter().map(ComplexToken::token).collect::<Vec<_>>()[..] {
+        [Number(number)] => Some(number.as_f64().to_string()),
+        [Operator((op, false)), Number(number)] if op.as_ref() == "-" => {
+            Some((-number.as_f64()).to_string())
+        }
+        [Symbol(symbol)] if matches!(symbol.as_ref(), "true" | "false" | "nil") => {
+            Some(symbol.to_string())
+        }
+        [Symbol(symbol)] if symbol.starts_with(['"', '\'']) => {
+            Some(symbol[1..symbol.len() - 1].to_owned())
+        }
+        _ => None,
+    }
+}
+
+// `subject == constant` or `constant == subject`
+fn equality(condition: &Expression) -> Option<(Expression, Expression)> {
+    use ComplexTokenKind::*;
+
+    let operators = condition
+        .iter()
+        .enumerate()
+        .filter(|(_, ctoken)| matches!(ctoken.token(), Operator((_, true))))
+        .collect::<Vec<_>>();
+    let [(i, operator)] = operators[..] else {
+        return None;
+    };
+    if !matches!(operator.token(), Operator((op, _)) if op.as_ref() == "==") {
+        return None;
+    }
+
+    let left = condition.range(..i).cloned().collect::<Expression>();
+    let right = condition.range(i + 1..).cloned().collect::<Expression>();
+    if constant(&right).is_some() {
+        Some((left, right))
+    } else {
+        Some((right, left))
+    }
+}
+
+pub(crate) struct MatchChain<'a> {
+    pub subject: Expression,
+    pub arms: Vec<(Expression, &'a CodeBlock)>,
+    pub default: Option<&'a CodeBlock>,
+}
+
+// an if chain comparing the same subject to at least two distinct constants
+pub(crate) fn match_chain<'a>(
+    condition: &Expression,
+    body: &'a CodeBlock,
+    next: &'a Option<Box<ComplexToken>>,
+) -> Option<MatchChain<'a>> {
+    let (subject, value) = equality(condition)?;
+    if !is_pure(&subject) || constant(&subject).is_some() {
+        return None;
+    }
+
+    let mut constants = vec![constant(&value)?];
+    let mut chain = MatchChain {
+        subject,
+        arms: vec![(value, body)],
+        default: None,
+    };
+
+    let mut next = next.as_deref().map(ComplexToken::token);
+    while let Some(ComplexTokenKind::IfStatement {
+        condition,
+        body,
+        next: after,
+    }) = next
+    {
+        if condition.is_empty() {
+            chain.default = Some(body);
+            break;
+        }
+
+        let (subject, value) = equality(condition)?;
+        let constant = constant(&value)?;
+        if !same_expression(&subject, &chain.subject) || constants.contains(&constant) {
+            return None;
+        }
+        constants.push(constant);
+        chain.arms.push((value, body));
+        next = after.as_deref().map(ComplexToken::token);
+    }
+
+    (chain.arms.len() >= 2).then_some(chain)
+}
+
+struct Walker<'a>(&'a mut dyn FnMut(&mut ComplexToken));
+
+impl VisitorMut for Walker<'_> {
+    fn visit_token(&mut self, ctoken: &mut ComplexToken) {
+        (self.0)(ctoken);
+        walk_token_mut(self, ctoken);
+    }
+}
+
+// calls `visit` on every token before descending into it
+fn walk<'a>(
+    tokens: impl IntoIterator<Item = &'a mut ComplexToken>,
+    visit: &mut dyn FnMut(&mut ComplexToken),
+) {
+    let mut walker = Walker(visit);
+    for ctoken in tokens {
+        walker.visit_token(ctoken);
+    }
+}
+
+fn is_name(expr: &Expression, name: &str) -> bool {
+    matches!(expr.front().map(ComplexToken::token), Some(ComplexTokenKind::Symbol(s)) if s.as_ref() == name)
+}
+
+fn is_target(ctoken: &ComplexToken, name: &str) -> bool {
+    matches!(ctoken.token(), ComplexTokenKind::Ident(expr) if is_name(expr, name))
+}
+
+fn declared_names(kind: &ComplexTokenKind) -> Vec<Arc<str>> {
+    use ComplexTokenKind::*;
+
+    match kind {
+        Variable { names, .. } => names.iter().map(|(name, _)| name.name.clone()).collect(),
+        Function { args, .. } | Method { args, .. } | Lambda { args, .. } => {
+            args.iter().map(|(arg, _)| arg.name.clone()).collect()
+        }
+        ForLoop { iter, .. } => vec![iter.name.clone()],
+        ForFuncLoop { iters, .. } => iters.iter().map(|iter| iter.name.clone()).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn mentions(expr: &mut Expression, name: &str) -> bool {
+    let mut found = false;
+    walk(expr.iter_mut(), &mut |ctoken| {
+        found |= is_target(ctoken, name)
+    });
+    found
+}
+
+// every name that is declared or read anywhere in the tree
+pub(crate) fn names(tree: &mut Expression) -> HashSet<Arc<str>> {
+    use ComplexTokenKind::*;
+
+    let mut names = HashSet::new();
+    walk(tree.iter_mut(), &mut |ctoken| {
+        names.extend(declared_names(ctoken.token()));
+        if let Ident(expr) | Function { name: expr, .. } | Method { name: expr, .. } =
+            ctoken.token()
+        {
+            if let Some(Symbol(name)) = expr.front().map(ComplexToken::token) {
+                names.insert(name.clone());
+            }
+        }
+    });
+    names
+}
+
+// `local Color = { RED = 1, GREEN = 2 }`
+fn enum_candidate(ctoken: &ComplexToken) -> Option<(Arc<str>, Vec<Arc<str>>)> {
+    use ComplexTokenKind::*;
+
+    let Variable { names, values } = ctoken.token() else {
+        return None;
+    };
+    let ([(name, false)], [value]) = (names.as_slice(), values.as_slice()) else {
+        return None;
+    };
+    let [Table(data)] = value.iter().map(ComplexToken::token).collect::<Vec<_>>()[..] else {
+        return None;
+    };
+
+    let mut keys = Vec::new();
+    for (key, value) in data {
+        let Some(Symbol(key)) = key
+            .as_ref()
+            .and_then(|key| key.front())
+            .map(ComplexToken::token)
+        else {
+            return None;
+        };
+        match value.iter().map(ComplexToken::token).collect::<Vec<_>>()[..] {
+            [Number(n)] if n.is_integer() => {}
+            // `not`, `~` and `#` don't make constants
+            [Operator((op, false)), Number(n)] if op.as_ref() == "-" && n.is_integer() => {}
+            _ => return None,
+        }
+        if key.starts_with(['"', '\'']) || keys.contains(key) {
+            return None;
+        }
+        keys.push(key.clone());
+    }
+
+    (!keys.is_empty()).then(|| (name.name.clone(), keys))
+}
+
+// why the table can't become an enum, judging by the statements after its declaration
+fn enum_rejection<'a>(
+    name: &str,
+    keys: &[Arc<str>],
+    rest: impl IntoIterator<Item = &'a mut ComplexToken>,
+) -> Option<String> {
+    use ComplexTokenKind::*;
+
+    let mut reason = None;
+    walk(rest, &mut |ctoken| {
+        let iterated = match ctoken.token_mut() {
+            ForFuncLoop { expr, .. } => mentions(expr, name),
+            _ => false,
+        };
+        let rejection = match ctoken.token() {
+            _ if declared_names(ctoken.token())
+                .iter()
+                .any(|n| n.as_ref() == name) =>
+            {
+                Some("it is shadowed".to_owned())
+            }
+            Alter { names, .. } if names.iter().any(|n| is_target(n, name)) => {
+                Some("it is assigned to".to_owned())
+            }
+            CompoundAlter { name: target, .. } if is_target(target, name) => {
+                Some("it is assigned to".to_owned())
+            }
+            Function { name: target, .. } | Method { name: target, .. }
+                if is_name(target, name) =>
+            {
+                Some("it is assigned to".to_owned())
+            }
+            ForFuncLoop { .. } if iterated => Some("it is iterated".to_owned()),
+            Ident(expr) if is_name(expr, name) => {
+                let access = expr.get(1).zip(expr.get(2));
+                match access.map(|(a, b)| (a.token(), b.token())) {
+                    Some((Symbol(dot), Symbol(key))) if dot.as_ref() == "." => {
+                        (!keys.contains(key)).then(|| format!("it has no key '{key}'"))
+                    }
+                    Some((Symbol(bracket), _)) if bracket.as_ref() == "[" => {
+                        Some("it is indexed with brackets".to_owned())
+                    }
+                    _ => Some("it is used as a value".to_owned()),
+                }
+            }
+            _ => None,
+        };
+        if reason.is_none() {
+            reason = rejection;
+        }
+    });
+
+    reason
+}
+
+// turns constant tables declared at the top level into enums, `Color.RED` becomes `RED`
+pub(crate) fn enums(
+    tree: &mut Expression,
+    path: Option<String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let names = names(tree);
+    let mut defined: Vec<Arc<str>> = Vec::new();
+
+    for i in 0..tree.len() {
+        let Some((name, keys)) = enum_candidate(&tree[i]) else {
+            continue;
+        };
+
+        let reason = enum_rejection(&name, &keys, tree.range_mut(i + 1..)).or_else(|| {
+            keys.iter()
+                .find(|key| names.contains(*key) || defined.contains(key))
+                .map(|key| format!("its key '{key}' is also used as a name"))
+        });
+        if let Some(reason) = reason {
+            diagnostics.push(
+                Diagnostic::new(
+                    format!("'{name}' was not turned into an enum because {reason}"),
+                    path.clone(),
+                    tree[i].position(),
+                )
+                .level(DiagnosticLevel::Note),
+            );
+            continue;
+        }
+
+        walk(tree.range_mut(i + 1..), &mut |ctoken| {
+            if let ComplexTokenKind::Ident(expr) = ctoken.token_mut() {
+                if is_name(expr, &name) {
+                    let leading = expr.pop_front().unwrap();
+                    expr.pop_front();
+                    expr[0].set_leading(leading.leading());
+                }
+            }
+        });
+
+        let ComplexTokenKind::Variable { values, .. } = tree[i].token_mut() else {
+            unreachable!()
+        };
+        let table = values.pop().unwrap();
+        *tree[i].token_mut() = ComplexTokenKind::Enum(table);
+        defined.extend(keys);
+    }
+}
+
+// a `break` that leaves the `repeat ... until true` wrapper, loops and functions have their own
+fn breaks_to_continues<'a>(tokens: impl IntoIterator<Item = &'a mut ComplexToken>) {
+    use ComplexTokenKind::*;
+
+    for ctoken in tokens {
+        match ctoken.token_mut() {
+            kind @ Break => *kind = Continue,
+            IfStatement { body, next, .. } => {
+                breaks_to_continues(body.code.iter_mut());
+                breaks_to_continues(next.iter_mut().map(Box::as_mut));
+            }
+            DoBlock(body) => breaks_to_continues(body.code.iter_mut()),
+            _ => {}
+        }
+    }
+}
+
+// `for ... do repeat ... until true end` emulates `continue` with `break`
+pub(crate) fn continues(tree: &mut Expression) {
+    use ComplexTokenKind::*;
+
+    walk(tree.iter_mut(), &mut |ctoken| {
+        let (WhileLoop { body, .. } | ForLoop { code: body, .. } | ForFuncLoop { code: body, .. }) =
+            ctoken.token_mut()
+        else {
+            return;
+        };
+        if body.code.len() != 1 {
+            return;
+        }
+        let RepeatLoop {
+            condition,
+            body: inner,
+        } = body.code[0].token_mut()
+        else {
+            return;
+        };
+        if !matches!(condition.iter().map(ComplexToken::token).collect::<Vec<_>>()[..], [Symbol(s)] if s.as_ref() == "true")
+        {
+            return;
+        }
+
+        let mut code = std::mem::take(&mut inner.code);
+        breaks_to_continues(code.iter_mut());
+        body.code = code;
+    });
+}
+
+fn local_name(ctoken: &ComplexToken) -> Option<&Arc<str>> {
+    use ComplexTokenKind::*;
+
+    match ctoken.token() {
+        Ident(expr) => match expr.iter().map(ComplexToken::token).collect::<Vec<_>>()[..] {
+            [Symbol(name)] => Some(name),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+// `if x == nil then x = default end`
+fn nil_default(ctoken: &ComplexToken) -> Option<(&Arc<str>, Expression)> {
+    use ComplexTokenKind::*;
+
+    let IfStatement {
+        condition,
+        body,
+        next: None,
+    } = ctoken.token()
+    else {
+        return None;
+    };
+    let (subject, value) = equality(condition)?;
+    let [Alter { names, values }] = body
+        .code
+        .iter()
+        .map(ComplexToken::token)
+        .collect::<Vec<_>>()[..]
+    else {
+        return None;
+    };
+    let ([name], [default]) = (names.as_slice(), values.as_slice()) else {
+        return None;
+    };
+    let name = local_name(name)?;
+    let [subject] = subject.iter().collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    // the `nil` token itself, a string like `"nil"` compares to the same constant
+    let [nil] = value.iter().map(ComplexToken::token).collect::<Vec<_>>()[..] else {
+        return None;
+    };
+    if local_name(subject) != Some(name) || !matches!(nil, Symbol(nil) if nil.as_ref() == "nil") {
+        return None;
+    }
+
+    Some((name, default.clone()))
+}
+
+// lifts the defaulting statements at the start of a function into its signature, in order
+// `x = x or default` is left alone, it also replaces `false` while a default parameter doesn't
+// arguments before `first` are implicit, like the `self` of a method, and can't have a default
+fn lift_defaults(args: &mut FunctionArgs, first: usize, body: &mut CodeBlock) {
+    let mut lifted = None;
+
+    while let Some((name, default)) = body.code.front().and_then(nil_default) {
+        let Some(i) = args
+            .iter()
+            .position(|(arg, default)| arg.name == *name && default.is_none())
+            .filter(|&i| i >= first && lifted.is_none_or(|lifted| i > lifted))
+        else {
+            break;
+        };
+
+        args[i].1 = Some(default);
+        body.code.pop_front();
+        lifted = Some(i);
+    }
+}
+
+pub(crate) fn default_parameters(tree: &mut Expression) {
+    use ComplexTokenKind::*;
+
+    walk(tree.iter_mut(), &mut |ctoken| match ctoken.token_mut() {
+        Function { args, body, .. } | Lambda { args, body, .. } => lift_defaults(args, 0, body),
+        Method { args, body, .. } => lift_defaults(args, 1, body),
+        _ => {}
+    });
+}
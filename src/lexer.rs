@@ -1,8 +1,8 @@
 use crate::{error::Diagnostic, number::Number};
-use collect_into_rc_slice::CollectIntoRcStr;
-use std::{fmt, ops::Range, rc::Rc};
+use std::{borrow::Cow, fmt, ops::Range, sync::Arc};
 
 #[derive(Debug,Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 #[rustfmt::skip]
 pub enum TokenType {
     // tokens
@@ -15,8 +15,17 @@ pub enum TokenType {
     // bitwise ops
     BitAnd, BitOr, BitShiftLeft, BitShiftRight,
 
+    // compound assignment (luau)
+    PlusEquals, MinusEquals, StarEquals, SlashEquals, FloorDivEquals,
+    PercentEquals, CaretEquals, DoubleDotEquals,
+
+    // types (luau)
+    DoubleColon, Arrow, Question,
+
     // literals
     Number, String, MultilineString, Identifier,
+    InterpolatedString, InterpolatedStringBegin, InterpolatedStringMiddle,
+    InterpolatedStringEnd,
 
     // keywords
     And, Break, Do, If, Else, ElseIf, End, True, False, Function,
@@ -27,13 +36,25 @@ pub enum TokenType {
     Eof
 }
 
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Dialect {
+    #[default]
+    Lua,
+    Luau,
+    #[cfg_attr(feature = "cli", value(name = "glua"))]
+    GLua,
+}
+
+// symbols borrow from the source, or from the input they are deserialized from when they can
 #[derive(Debug, Clone, PartialEq)]
-pub enum Lexeme {
-    Symbol(Rc<str>),
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Lexeme<'a> {
+    Symbol(#[cfg_attr(feature = "serde", serde(borrow))] Cow<'a, str>),
     Number(Number),
 }
 
-impl fmt::Display for Lexeme {
+impl fmt::Display for Lexeme<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             Lexeme::Symbol(s) => write!(f, "{}", s),
@@ -42,14 +63,18 @@ impl fmt::Display for Lexeme {
     }
 }
 
-impl Lexeme {
-    pub fn as_symbol(&self) -> Rc<str> {
+impl Lexeme<'_> {
+    pub fn as_str(&self) -> &str {
         match self {
-            Lexeme::Symbol(s) => s.clone(),
+            Lexeme::Symbol(s) => s,
             _ => panic!("lexeme is not a symbol"),
         }
     }
 
+    pub fn as_symbol(&self) -> Arc<str> {
+        Arc::from(self.as_str())
+    }
+
     pub fn as_number(&self) -> &Number {
         match self {
             Lexeme::Number(n) => n,
@@ -59,6 +84,7 @@ impl Lexeme {
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Position {
     pub line: usize,
     pub column: usize,
@@ -80,28 +106,32 @@ impl Position {
 }
 
 #[derive(Debug, Clone, PartialEq)]
-pub struct Token {
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Token<'a> {
     pub(crate) kind: TokenType,
-    lexeme: Lexeme,
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    lexeme: Lexeme<'a>,
     position: Position,
-    leading: String,
-    trailing: String,
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    leading: Cow<'a, str>,
+    #[cfg_attr(feature = "serde", serde(borrow))]
+    trailing: Cow<'a, str>,
 }
 
-impl Token {
+impl<'a> Token<'a> {
     pub const fn new(
         kind: TokenType,
-        lexeme: Rc<str>,
+        lexeme: &'a str,
         position: Position,
-        leading: String,
-        trailing: String,
+        leading: Cow<'a, str>,
+        trailing: &'a str,
     ) -> Self {
         Self {
             kind,
-            lexeme: Lexeme::Symbol(lexeme),
+            lexeme: Lexeme::Symbol(Cow::Borrowed(lexeme)),
             position,
             leading,
-            trailing,
+            trailing: Cow::Borrowed(trailing),
         }
     }
 
@@ -109,15 +139,15 @@ impl Token {
         kind: TokenType,
         lexeme: Number,
         position: Position,
-        leading: String,
-        trailing: String,
+        leading: Cow<'a, str>,
+        trailing: &'a str,
     ) -> Self {
         Self {
             kind,
             lexeme: Lexeme::Number(lexeme),
             position,
             leading,
-            trailing,
+            trailing: Cow::Borrowed(trailing),
         }
     }
 
@@ -125,7 +155,7 @@ impl Token {
         self.kind
     }
 
-    pub const fn lexeme(&self) -> &Lexeme {
+    pub const fn lexeme(&self) -> &Lexeme<'a> {
         &self.lexeme
     }
 
@@ -145,36 +175,51 @@ impl Token {
         self.position.span.clone()
     }
 
-    pub fn leading(&self) -> String {
-        self.leading.clone()
+    pub fn leading(&self) -> &str {
+        &self.leading
     }
 
-    pub fn trailing(&self) -> String {
-        self.trailing.clone()
+    pub fn trailing(&self) -> &str {
+        &self.trailing
     }
 }
 
-pub struct Lexer {
-    pub(crate) source: Vec<char>,
-    tokens: Vec<Token>,
-    leading: String,
+pub struct Lexer<'a> {
+    pub(crate) source: &'a str,
+    // trivia stays borrowed until a comment that is left out splits it
+    leading: Cow<'a, str>,
+    leading_end: usize,
+    // comments are kept in the trivia, so the source can be printed back
+    comments: bool,
+    dialect: Dialect,
+    braces: Vec<bool>,
     pub(crate) path: Option<String>,
     pub(crate) current: usize,
     pub(crate) position: Position,
+    pub(crate) diagnostics: Vec<Diagnostic>,
 }
 
-impl Lexer {
-    pub fn new(source: String, path: Option<String>) -> Self {
+impl<'a> Lexer<'a> {
+    pub fn new(source: &'a str, path: Option<String>, dialect: Dialect) -> Self {
         Self {
-            source: source.chars().collect(),
-            tokens: Vec::new(),
-            leading: String::new(),
+            source,
+            leading: Cow::Borrowed(""),
+            leading_end: 0,
+            comments: true,
+            dialect,
+            braces: Vec::new(),
             path,
             current: 0,
             position: Position::new(1, 0, 0..0),
+            diagnostics: Vec::new(),
         }
     }
 
+    // warnings found so far, scanning goes on after them
+    pub fn take_diagnostics(&mut self) -> Vec<Diagnostic> {
+        std::mem::take(&mut self.diagnostics)
+    }
+
     pub(crate) const fn line(&self) -> usize {
         self.position.line
     }
@@ -187,122 +232,149 @@ impl Lexer {
         self.current >= self.source.len()
     }
 
-    pub(crate) fn advance(&mut self) -> Option<char> {
-        if self.done() {
-            None
-        } else {
-            if self.source[self.current] == '\n' {
-                self.position.line += 1;
-                self.position.column = 0;
-            }
-            let result = Some(self.source[self.current]);
-            self.current += 1;
-            self.position.column += 1;
+    fn char_before(&self, offset: usize) -> Option<char> {
+        self.source[..offset].chars().next_back()
+    }
 
-            result
+    pub(crate) fn advance(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        if c == '\n' {
+            self.position.line += 1;
+            self.position.column = 0;
         }
+        self.current += c.len_utf8();
+        self.position.column += 1;
+
+        Some(c)
     }
 
     fn advance_to(&mut self, offset: usize) -> Option<char> {
-        if self.done() {
-            None
-        } else {
-            if self.source[self.current + offset - 1] == '\n' {
-                self.position.line += 1;
-                self.position.column = 0;
-            }
-            let result = Some(self.source[self.current + offset - 1]);
-            self.current += offset;
-            self.position.column += offset;
-            result
+        let mut result = None;
+        for _ in 0..offset {
+            result = self.advance();
         }
+        result
     }
 
     pub(crate) fn go_back(&mut self) -> Option<char> {
-        if self.current == 0 {
-            None
+        let c = self.char_before(self.current)?;
+        if c == '\n' {
+            self.position.line -= 1;
+            self.position.column = 0;
         } else {
-            if self.source[self.current - 1] == '\n' {
-                self.position.line -= 1;
-                self.position.column = 0;
-            } else {
-                self.position.column -= 1;
-            }
-            self.current -= 1;
-
-            Some(self.source[self.current])
+            self.position.column -= 1;
         }
+        self.current -= c.len_utf8();
+
+        Some(c)
     }
 
     pub(crate) fn peek(&self) -> Option<char> {
-        if self.done() {
-            None
-        } else {
-            self.source.get(self.current).copied()
-        }
+        self.source[self.current..].chars().next()
     }
 
     fn peek_at(&self, offset: usize) -> Option<char> {
-        if self.done() {
-            None
-        } else {
-            self.source.get(self.current + offset - 1).copied()
-        }
+        self.source[self.current..].chars().nth(offset - 1)
     }
 
     pub(crate) fn look_back(&self) -> Option<char> {
-        (self.current != 0).then_some(self.source[self.current - 2])
+        let previous = self.char_before(self.current)?;
+        self.char_before(self.current - previous.len_utf8())
+    }
+
+    fn push_leading(&mut self, start: usize) {
+        match self.leading {
+            Cow::Borrowed(leading) if leading.is_empty() || self.leading_end == start => {
+                self.leading = Cow::Borrowed(&self.source[start - leading.len()..self.current]);
+            }
+            _ => self
+                .leading
+                .to_mut()
+                .push_str(&self.source[start..self.current]),
+        }
+        self.leading_end = self.current;
     }
 
-    fn collect_trailing(&mut self) -> String {
-        let mut trailing = String::new();
+    fn push_comment(&mut self, start: usize) {
+        if self.comments {
+            self.push_leading(start);
+        }
+    }
+
+    fn take_leading(&mut self) -> Cow<'a, str> {
+        std::mem::replace(&mut self.leading, Cow::Borrowed(""))
+    }
+
+    fn collect_trailing(&mut self) -> &'a str {
+        let start = self.current;
         while let Some(c) = self.advance() {
-            if matches!(c, ' ' | '\r' | '\t' | '\n') {
-                trailing.push(c);
-            } else {
+            if !matches!(c, ' ' | '\r' | '\t' | '\n') {
                 self.go_back();
                 break;
             }
         }
-        trailing
+
+        // comments at the end of the file have no token to lead, the last token keeps them
+        if self.comments && matches!(self.peek(), Some('-' | '/' | '#')) {
+            let mut rest = Lexer {
+                source: self.source,
+                leading: Cow::Borrowed(""),
+                leading_end: self.current,
+                comments: false,
+                dialect: self.dialect,
+                braces: self.braces.clone(),
+                path: None,
+                current: self.current,
+                position: self.position.clone(),
+                diagnostics: Vec::new(),
+            };
+            if let Ok(None) = rest.scan_token() {
+                self.current = rest.current;
+                self.position = rest.position;
+            }
+        }
+        &self.source[start..self.current]
     }
 
-    fn add_token(&mut self, token_type: TokenType, len: usize) {
-        let span = self.current - len..self.current;
-        let lexeme = self.source[span.clone()]
-            .iter()
-            .copied()
-            .collect_into_rc_str();
+    fn make_token(&mut self, token_type: TokenType, span: Range<usize>) -> Token<'a> {
+        let lexeme = &self.source[span.clone()];
         let trailing = self.collect_trailing();
 
-        self.tokens.push(Token::new(
+        Token::new(
             token_type,
             lexeme,
-            Position::new(self.position.line, self.position.column, span),
-            self.leading.drain(..).collect(),
+            self.position.with_span(span),
+            self.take_leading(),
             trailing,
-        ));
+        )
     }
 
-    fn add_token_front(&mut self, token_type: TokenType, len: usize) {
-        let span = self.current - 1..self.current + len - 1;
-        let lexeme = self.source[span.clone()]
-            .iter()
-            .copied()
-            .collect_into_rc_str();
-        self.advance_to(len - 1);
-        let trailing = self.collect_trailing();
+    fn add_token(&mut self, token_type: TokenType, len: usize) -> Token<'a> {
+        let span = self.current - len..self.current;
+        self.make_token(token_type, span)
+    }
+
+    fn add_compound_token(
+        &mut self,
+        token_type: TokenType,
+        compound: TokenType,
+        len: usize,
+    ) -> Token<'a> {
+        if self.dialect == Dialect::Luau && self.peek() == Some('=') {
+            self.advance();
+            self.add_token(compound, len + 1)
+        } else {
+            self.add_token(token_type, len)
+        }
+    }
 
-        self.tokens.push(Token::new(
-            token_type,
-            lexeme,
-            Position::new(self.position.line, self.position.column, span),
-            self.leading.drain(..).collect(),
-            trailing,
-        ));
+    fn add_token_front(&mut self, token_type: TokenType, len: usize) -> Token<'a> {
+        let start = self.current - 1;
+        self.advance_to(len - 1);
+        self.add_token(token_type, self.current - start)
     }
 
-    fn read_string(&mut self, quote: char) -> Result<(), Diagnostic> {
+    fn read_string(&mut self, quote: char) -> Result<Token<'a>, Diagnostic> {
         let start = self.current;
         while self.peek() != Some(quote) && !self.done() {
             if self.peek() == Some('\\') {
@@ -332,25 +404,49 @@ impl Lexer {
             ));
         }
         self.advance();
-        let span = start - 1..self.current;
-        let lexeme = self.source[span.clone()]
-            .iter()
-            .copied()
-            .collect_into_rc_str();
-        let trailing = self.collect_trailing();
 
-        self.tokens.push(Token::new(
-            TokenType::String,
-            lexeme,
-            self.position.with_span(span),
-            self.leading.drain(..).collect(),
-            trailing,
-        ));
+        Ok(self.make_token(TokenType::String, start - 1..self.current))
+    }
 
-        Ok(())
+    fn read_interpolated_string(&mut self, first: bool) -> Result<Token<'a>, Diagnostic> {
+        let start = self.current - 1;
+
+        let kind = loop {
+            match self.advance() {
+                Some('\\') => {
+                    if self.advance().is_none() {
+                        return Err(Diagnostic::new(
+                            "unterminated escape sequence".to_owned(),
+                            self.path.clone(),
+                            self.position.clone(),
+                        ));
+                    }
+                }
+                Some('`') if first => break TokenType::InterpolatedString,
+                Some('`') => break TokenType::InterpolatedStringEnd,
+                Some('{') => {
+                    self.braces.push(true);
+                    if first {
+                        break TokenType::InterpolatedStringBegin;
+                    } else {
+                        break TokenType::InterpolatedStringMiddle;
+                    }
+                }
+                Some(_) => {}
+                None => {
+                    return Err(Diagnostic::new(
+                        "unterminated interpolated string".to_owned(),
+                        self.path.clone(),
+                        self.position.with_span(start..self.current),
+                    ));
+                }
+            }
+        };
+
+        Ok(self.add_token(kind, self.current - start))
     }
 
-    fn read_multiline_string(&mut self) -> Result<(), Diagnostic> {
+    fn read_multiline_string(&mut self) -> Result<Token<'a>, Diagnostic> {
         let start = self.current;
         let mut equals_count = 0;
 
@@ -399,25 +495,11 @@ impl Lexer {
         }
 
         self.advance();
-        let span = start - 1..self.current;
-        let lexeme = self.source[span.clone()]
-            .iter()
-            .copied()
-            .collect_into_rc_str();
-        let trailing = self.collect_trailing();
-
-        self.tokens.push(Token::new(
-            TokenType::MultilineString,
-            lexeme,
-            self.position.with_span(span),
-            self.leading.drain(..).collect(),
-            trailing,
-        ));
 
-        Ok(())
+        Ok(self.make_token(TokenType::MultilineString, start - 1..self.current))
     }
 
-    fn read_token(&mut self) -> Result<(), Diagnostic> {
+    fn read_token(&mut self) -> Result<Token<'a>, Diagnostic> {
         let start = self.current;
 
         if let Some(c) = self.peek() {
@@ -435,12 +517,8 @@ impl Lexer {
                 }
             }
             let span = start..self.current;
-            let lexeme = self.source[span.clone()]
-                .iter()
-                .copied()
-                .collect_into_rc_str();
 
-            let kind = match &*lexeme {
+            let kind = match &self.source[span.clone()] {
                 "and" => TokenType::And,
                 "break" => TokenType::Break,
                 "do" => TokenType::Do,
@@ -471,17 +549,8 @@ impl Lexer {
                 }
                 _ => TokenType::Identifier,
             };
-            let trailing = self.collect_trailing();
-
-            self.tokens.push(Token::new(
-                kind,
-                lexeme,
-                self.position.with_span(span),
-                self.leading.drain(..).collect(),
-                trailing,
-            ));
 
-            Ok(())
+            Ok(self.make_token(kind, span))
         } else {
             Err(Diagnostic::new(
                 "invalid identifier".to_owned(),
@@ -491,21 +560,36 @@ impl Lexer {
         }
     }
 
-    fn read_number(&mut self) -> Result<(), Diagnostic> {
+    fn read_number(&mut self) -> Result<Token<'a>, Diagnostic> {
         let start = self.current;
         let number = Number::from_source(self)?;
-        let span = start - 1..self.current;
+        let span = start - self.char_before(start).map_or(0, char::len_utf8)..self.current;
         let trailing = self.collect_trailing();
 
-        self.tokens.push(Token::new_number(
+        Ok(Token::new_number(
             TokenType::Number,
             number,
             self.position.with_span(span),
-            self.leading.drain(..).collect(),
+            self.take_leading(),
             trailing,
-        ));
+        ))
+    }
 
-        Ok(())
+    fn read_c_comment(&mut self) -> Result<(), Diagnostic> {
+        let start = self.current - 2;
+
+        while let Some(c) = self.advance() {
+            if c == '*' && self.peek() == Some('/') {
+                self.advance();
+                return Ok(());
+            }
+        }
+
+        Err(Diagnostic::new(
+            "unterminated block comment".to_owned(),
+            self.path.clone(),
+            self.position.with_span(start..self.current),
+        ))
     }
 
     fn read_multiline_comment(&mut self) -> Result<(), Diagnostic> {
@@ -562,154 +646,244 @@ impl Lexer {
         self.advance();
         Ok(())
     }
-}
 
-pub fn scan_code(code: String, path: Option<String>) -> Result<Vec<Token>, Diagnostic> {
-    let mut lexer = Lexer::new(code, path);
-
-    while let Some(c) = lexer.advance() {
-        match c {
-            ' ' | '\r' | '\t' | '\n' => {
-                lexer.leading.push(c);
+    fn skip_line(&mut self) {
+        while let Some(c) = self.advance() {
+            if c == '\n' {
+                break;
             }
-            '(' => lexer.add_token(TokenType::LeftParen, 1),
-            ')' => lexer.add_token(TokenType::RightParen, 1),
-            '{' => lexer.add_token(TokenType::LeftBrace, 1),
-            '}' => lexer.add_token(TokenType::RightBrace, 1),
-            '[' => {
-                if lexer.peek().map_or(false, |c| c == '[' || c == '=') {
-                    lexer.read_multiline_string()?;
+        }
+    }
+
+    fn scan_token(&mut self) -> Result<Option<Token<'a>>, Diagnostic> {
+        while let Some(c) = self.advance() {
+            let start = self.current - c.len_utf8();
+            let token = match c {
+                ' ' | '\r' | '\t' | '\n' => {
+                    self.push_leading(self.current - 1);
                     continue;
                 }
-                lexer.add_token(TokenType::LeftBracket, 1)
-            }
-            ']' => lexer.add_token(TokenType::RightBracket, 1),
-            '+' => lexer.add_token(TokenType::Plus, 1),
-            '-' => match (lexer.peek(), lexer.peek_at(2)) {
-                (Some('-'), Some('[')) => {
-                    if matches!(lexer.peek_at(3), Some('[' | '=')) {
-                        lexer.advance_to(2);
-                        lexer.read_multiline_comment()?;
-                        continue;
+                '(' => self.add_token(TokenType::LeftParen, 1),
+                ')' => self.add_token(TokenType::RightParen, 1),
+                '{' => {
+                    self.braces.push(false);
+                    self.add_token(TokenType::LeftBrace, 1)
+                }
+                '}' => {
+                    if self.braces.pop() == Some(true) {
+                        self.read_interpolated_string(false)?
                     } else {
-                        lexer.advance_to(2);
-                        while let Some(c) = lexer.advance() {
-                            if c == '\n' {
-                                break;
-                            }
-                        }
+                        self.add_token(TokenType::RightBrace, 1)
                     }
                 }
-                (Some('-'), _) => {
-                    lexer.advance();
-                    while let Some(c) = lexer.advance() {
-                        if c == '\n' {
-                            break;
-                        }
+                '[' => {
+                    if self.peek().is_some_and(|c| c == '[' || c == '=') {
+                        self.read_multiline_string()?
+                    } else {
+                        self.add_token(TokenType::LeftBracket, 1)
                     }
                 }
-                _ => lexer.add_token(TokenType::Minus, 1),
-            },
-            '#' => {
-                if lexer.line() == 1 && lexer.column() == 1 {
-                    while let Some(c) = lexer.advance() {
-                        if c == '\n' {
-                            break;
+                ']' => self.add_token(TokenType::RightBracket, 1),
+                '+' => self.add_compound_token(TokenType::Plus, TokenType::PlusEquals, 1),
+                '-' => match (self.peek(), self.peek_at(2)) {
+                    (Some('-'), Some('[')) => {
+                        self.advance_to(2);
+                        if matches!(self.peek(), Some('[' | '=')) {
+                            self.read_multiline_comment()?;
+                        } else {
+                            self.skip_line();
                         }
+                        self.push_comment(start);
+                        continue;
                     }
-                    continue;
+                    (Some('-'), _) => {
+                        self.advance();
+                        self.skip_line();
+                        self.push_comment(start);
+                        continue;
+                    }
+                    (Some('>'), _) if self.dialect == Dialect::Luau => {
+                        self.advance();
+                        self.add_token(TokenType::Arrow, 2)
+                    }
+                    _ => self.add_compound_token(TokenType::Minus, TokenType::MinusEquals, 1),
+                },
+                '#' => {
+                    if self.line() == 1 && self.column() == 1 {
+                        self.skip_line();
+                        self.push_comment(start);
+                        continue;
+                    }
+                    self.add_token(TokenType::Hash, 1)
                 }
-                lexer.add_token(TokenType::Hash, 1)
-            }
-            '*' => lexer.add_token(TokenType::Star, 1),
-            '/' => {
-                if let Some('/') = lexer.peek() {
-                    lexer.advance();
-                    lexer.add_token(TokenType::FloorDiv, 2)
-                } else {
-                    lexer.add_token(TokenType::Slash, 1)
+                '*' => self.add_compound_token(TokenType::Star, TokenType::StarEquals, 1),
+                '/' if self.dialect == Dialect::GLua => match self.peek() {
+                    Some('/') => {
+                        self.skip_line();
+                        self.push_comment(start);
+                        continue;
+                    }
+                    Some('*') => {
+                        self.advance();
+                        self.read_c_comment()?;
+                        self.push_comment(start);
+                        continue;
+                    }
+                    _ => self.add_token(TokenType::Slash, 1),
+                },
+                '/' => {
+                    if let Some('/') = self.peek() {
+                        self.advance();
+                        self.add_compound_token(TokenType::FloorDiv, TokenType::FloorDivEquals, 2)
+                    } else {
+                        self.add_compound_token(TokenType::Slash, TokenType::SlashEquals, 1)
+                    }
                 }
-            }
-            '%' => lexer.add_token(TokenType::Percent, 1),
-            '^' => lexer.add_token(TokenType::Caret, 1),
-            '=' => {
-                if let Some('=') = lexer.peek() {
-                    lexer.advance();
-                    lexer.add_token(TokenType::DoubleEquals, 2);
-                } else {
-                    lexer.add_token(TokenType::Equals, 1);
+                '%' => self.add_compound_token(TokenType::Percent, TokenType::PercentEquals, 1),
+                '^' => self.add_compound_token(TokenType::Caret, TokenType::CaretEquals, 1),
+                '=' => {
+                    if let Some('=') = self.peek() {
+                        self.advance();
+                        self.add_token(TokenType::DoubleEquals, 2)
+                    } else {
+                        self.add_token(TokenType::Equals, 1)
+                    }
                 }
-            }
-            '~' => {
-                if let Some('=') = lexer.peek() {
-                    lexer.advance();
-                    lexer.add_token(TokenType::NotEquals, 2);
-                } else {
-                    lexer.add_token(TokenType::Tilde, 1);
+                '~' => {
+                    if let Some('=') = self.peek() {
+                        self.advance();
+                        self.add_token(TokenType::NotEquals, 2)
+                    } else {
+                        self.add_token(TokenType::Tilde, 1)
+                    }
                 }
-            }
-            '<' => match lexer.peek() {
-                Some('=') => {
-                    lexer.advance();
-                    lexer.add_token(TokenType::LessThanOrEqual, 2);
+                '<' => match self.peek() {
+                    Some('=') => {
+                        self.advance();
+                        self.add_token(TokenType::LessThanOrEqual, 2)
+                    }
+                    Some('<') => {
+                        self.advance();
+                        self.add_token(TokenType::BitShiftLeft, 2)
+                    }
+                    _ => self.add_token(TokenType::LessThan, 1),
+                },
+                '>' => match self.peek() {
+                    Some('=') => {
+                        self.advance();
+                        self.add_token(TokenType::GreaterThanOrEqual, 2)
+                    }
+                    Some('>') => {
+                        self.advance();
+                        self.add_token(TokenType::BitShiftRight, 2)
+                    }
+                    _ => self.add_token(TokenType::GreaterThan, 1),
+                },
+                '.' => match (self.peek(), self.peek_at(2)) {
+                    (Some('.'), Some('.')) => self.add_token_front(TokenType::TripleDot, 3),
+                    (Some('0'..='9'), _) => {
+                        self.current -= 1;
+                        self.read_number()?
+                    }
+                    (Some('.'), Some('=')) if self.dialect == Dialect::Luau => {
+                        self.add_token_front(TokenType::DoubleDotEquals, 3)
+                    }
+                    (Some('.'), _) => self.add_token_front(TokenType::DoubleDot, 2),
+                    _ => self.add_token(TokenType::Dot, 1),
+                },
+                ':' => {
+                    if let Some(':') = self.peek() {
+                        if self.dialect != Dialect::Luau {
+                            return Err(Diagnostic::new(
+                                "labels are not supported".to_owned(),
+                                self.path.clone(),
+                                self.position.with_span(self.current - 1..self.current),
+                            ));
+                        }
+                        self.advance();
+                        self.add_token(TokenType::DoubleColon, 2)
+                    } else {
+                        self.add_token(TokenType::Colon, 1)
+                    }
                 }
-                Some('<') => {
-                    lexer.advance();
-                    lexer.add_token(TokenType::BitShiftLeft, 2);
+                ';' => self.add_token(TokenType::Semicolon, 1),
+                ',' => self.add_token(TokenType::Comma, 1),
+                '&' if self.dialect == Dialect::GLua && self.peek() == Some('&') => {
+                    self.advance();
+                    self.add_token(TokenType::And, 2)
                 }
-                _ => lexer.add_token(TokenType::LessThan, 1),
-            },
-            '>' => match lexer.peek() {
-                Some('=') => {
-                    lexer.advance();
-                    lexer.add_token(TokenType::GreaterThanOrEqual, 2);
+                '|' if self.dialect == Dialect::GLua && self.peek() == Some('|') => {
+                    self.advance();
+                    self.add_token(TokenType::Or, 2)
                 }
-                Some('>') => {
-                    lexer.advance();
-                    lexer.add_token(TokenType::BitShiftRight, 2);
+                '!' if self.dialect == Dialect::GLua => {
+                    if let Some('=') = self.peek() {
+                        self.advance();
+                        self.add_token(TokenType::NotEquals, 2)
+                    } else {
+                        self.add_token(TokenType::Not, 1)
+                    }
                 }
-                _ => lexer.add_token(TokenType::GreaterThan, 1),
-            },
-            '.' => match (lexer.peek(), lexer.peek_at(2)) {
-                (Some('.'), Some('.')) => lexer.add_token_front(TokenType::TripleDot, 3),
-                (Some('0'..='9'), _) => {
-                    lexer.current -= 1;
-                    lexer.read_number()?
+                '&' => self.add_token(TokenType::BitAnd, 1),
+                '|' => self.add_token(TokenType::BitOr, 1),
+                '?' if self.dialect == Dialect::Luau => self.add_token(TokenType::Question, 1),
+                '"' | '\'' => self.read_string(c)?,
+                '`' if self.dialect == Dialect::Luau => self.read_interpolated_string(true)?,
+                _ if c.is_ascii_digit() => {
+                    self.go_back();
+                    self.read_number()?
                 }
-                (Some('.'), _) => lexer.add_token_front(TokenType::DoubleDot, 2),
-                _ => lexer.add_token(TokenType::Dot, 1),
-            },
-            ':' => {
-                if let Some(':') = lexer.peek() {
-                    return Err(Diagnostic::new(
-                        "labels are not supported".to_owned(),
-                        lexer.path.clone(),
-                        lexer.position.with_span(lexer.current - 1..lexer.current),
-                    ));
+                _ if c.is_ascii_alphabetic() || c == '_' => {
+                    self.go_back();
+                    self.read_token()?
                 }
-                lexer.add_token(TokenType::Colon, 1)
-            }
-            ';' => lexer.add_token(TokenType::Semicolon, 1),
-            ',' => lexer.add_token(TokenType::Comma, 1),
-            '&' => lexer.add_token(TokenType::BitAnd, 1),
-            '|' => lexer.add_token(TokenType::BitOr, 1),
-            '"' | '\'' => {
-                lexer.read_string(c)?;
-            }
-            _ => {
-                lexer.go_back();
-                if c.is_ascii_digit() {
-                    lexer.read_number()?;
-                } else if c.is_ascii_alphabetic() || c == '_' {
-                    lexer.read_token()?;
-                } else {
-                    panic!(
-                        "{}",
-                        Diagnostic::unexpected(c.to_string(), lexer.path, lexer.position)
-                    );
+                // the character stays consumed, so the lexer can go on after the error
+                _ => {
+                    return Err(Diagnostic::unexpected(
+                        c.to_string(),
+                        self.path.clone(),
+                        self.position
+                            .with_span(self.current - c.len_utf8()..self.current),
+                    ))
                 }
-            }
+            };
+
+            return Ok(Some(token));
         }
+
+        Ok(None)
     }
-    Ok(lexer.tokens)
+}
+
+impl<'a> Iterator for Lexer<'a> {
+    type Item = Result<Token<'a>, Diagnostic>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.scan_token().transpose()
+    }
+}
+
+// the whitespace of some trivia, without the comments in it
+pub(crate) fn strip_comments(trivia: &str) -> Cow<'_, str> {
+    if trivia.chars().all(char::is_whitespace) {
+        return Cow::Borrowed(trivia);
+    }
+    // trivia only holds `//` comments in GLua, and nothing else would start with a `/`
+    let mut lexer = Lexer::new(trivia, None, Dialect::GLua);
+    lexer.comments = false;
+    // comments were already checked when the trivia was scanned
+    let _ = lexer.scan_token();
+    lexer.take_leading()
+}
+
+pub fn scan_code<'a>(
+    code: &'a str,
+    path: Option<String>,
+    dialect: Dialect,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Vec<Token<'a>>, Diagnostic> {
+    let mut lexer = Lexer::new(code, path, dialect);
+    let scanned = lexer.by_ref().collect();
+    diagnostics.extend(lexer.take_diagnostics());
+    scanned
 }
@@ -1,5 +1,14 @@
 pub mod compiler;
+mod convert;
 pub mod error;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+mod idioms;
 pub mod lexer;
+pub mod lua_printer;
 mod number;
 pub mod parser;
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+mod wasm;
+
+pub use convert::{convert, ConvertOptions, ConvertOutput};
@@ -0,0 +1,558 @@
+use crate::parser::{CodeBlock, ComplexToken, ComplexTokenKind, Expression, TypedName};
+use std::sync::Arc;
+
+const KEYWORDS: [&str; 22] = [
+    "and", "break", "do", "else", "elseif", "end", "false", "for", "function", "goto", "if", "in",
+    "local", "nil", "not", "or", "repeat", "return", "then", "true", "until", "while",
+];
+
+// whether two tokens would lex differently when written next to each other
+fn needs_space(prev: char, next: char) -> bool {
+    let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+    (is_word(prev) && is_word(next))
+        || (prev.is_ascii_digit() && next == '.')
+        || (prev == '.' && next.is_ascii_digit())
+        || matches!(
+            (prev, next),
+            ('-', '-')
+                | ('.', '.')
+                | ('[', '[' | '=')
+                | ('=' | '~' | '<' | '>' | '!', '=')
+                | ('<', '<')
+                | ('>', '>')
+                | ('/', '/')
+                | (':', ':')
+                | ('&', '&')
+                | ('|', '|')
+        )
+}
+
+fn push(result: &mut String, token: &str) {
+    if let (Some(prev), Some(next)) = (result.chars().last(), token.chars().next()) {
+        if needs_space(prev, next) {
+            result.push(' ');
+        }
+    }
+    result.push_str(token);
+}
+
+fn push_keyword(result: &mut String, keyword: &str) {
+    if !result.is_empty() && !result.ends_with(char::is_whitespace) {
+        result.push(' ');
+    }
+    result.push_str(keyword);
+}
+
+// trailing trivia shared with the last printed token must not be printed twice
+fn push_trailing(result: &mut String, trailing: &str) {
+    if !result.ends_with(trailing) {
+        result.push_str(trailing);
+    }
+}
+
+fn push_trivia_or_space(result: &mut String, trivia: &str) {
+    if trivia.is_empty() {
+        result.push(' ');
+    } else {
+        result.push_str(trivia);
+    }
+}
+
+fn trim_end(result: &mut String) {
+    let len = result.trim_end().len();
+    result.truncate(len);
+}
+
+fn is_name(key: &Expression) -> bool {
+    match key.iter().next().map(|key| key.token()) {
+        Some(ComplexTokenKind::Symbol(name)) if key.len() == 1 => {
+            name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+                && !KEYWORDS.contains(&name.as_ref())
+        }
+        _ => false,
+    }
+}
+
+fn print_token(result: &mut String, ctoken: &ComplexToken, lexeme: &str) {
+    result.push_str(ctoken.leading());
+    push(result, lexeme);
+    result.push_str(ctoken.trailing());
+}
+
+fn print_list<T>(result: &mut String, list: &[T], print: &mut impl FnMut(&mut String, &T)) {
+    for (i, element) in list.iter().enumerate() {
+        if i != 0 {
+            result.push_str(", ");
+        }
+        print(result, element);
+    }
+}
+
+fn print_expressions(result: &mut String, exprs: &[Expression]) {
+    print_list(result, exprs, &mut print_expression);
+}
+
+fn print_typed_name(result: &mut String, name: &TypedName) {
+    push(result, &name.name);
+    if let Some(ty) = &name.ty {
+        result.push_str(": ");
+        result.push_str(ty);
+    }
+}
+
+fn print_call(result: &mut String, call: &ComplexToken, args: &[Expression]) {
+    if let [arg] = args {
+        if let (1, Some(ComplexTokenKind::Symbol(string))) =
+            (arg.len(), arg.front().map(|arg| arg.token()))
+        {
+            // f[[...]] is parsed into a symbol instead of a multiline string, keep it that way
+            let sugar = string.starts_with('[')
+                || (string.starts_with(['"', '\''])
+                    && !call.trailing().is_empty()
+                    && arg[0].trailing() == call.trailing());
+            if sugar {
+                return print_token(result, &arg[0], string);
+            }
+        }
+    }
+
+    push(result, "(");
+    // calls with a single string or table argument share their trivia with it
+    if !(args.len() == 1 && args[0].len() == 1 && args[0][0].trailing() == call.trailing()) {
+        result.push_str(call.trailing());
+    }
+    print_expressions(result, args);
+    trim_end(result);
+    result.push(')');
+}
+
+fn print_identifier(result: &mut String, ident: &ComplexToken) {
+    use ComplexTokenKind::*;
+
+    let Ident(expr) = ident.token() else {
+        unreachable!()
+    };
+
+    for ctoken in expr {
+        match ctoken.token() {
+            // '.', ':' and ']' carry the trivia of the tokens next to them
+            Symbol(lexeme) if matches!(lexeme.as_ref(), "." | ":" | "]") => push(result, lexeme),
+            Symbol(lexeme) => print_token(result, ctoken, lexeme),
+            Expr(index) => print_expression(result, index),
+            Call(args) => print_call(result, ctoken, args),
+            _ => unreachable!(),
+        }
+    }
+    push_trailing(result, ident.trailing());
+}
+
+fn print_table(
+    result: &mut String,
+    table: &ComplexToken,
+    data: &[(Option<Expression>, Expression)],
+) {
+    push(result, "{");
+    print_list(result, data, &mut |result, (key, value)| {
+        match key {
+            Some(key) if is_name(key) => print_expression(result, key),
+            Some(key) => {
+                push(result, "[");
+                print_expression(result, key);
+                push(result, "]");
+            }
+            None => {
+                print_expression(result, value);
+                return;
+            }
+        }
+        push_keyword(result, "= ");
+        print_expression(result, value);
+    });
+    result.push_str(table.leading());
+    push(result, "}");
+    result.push_str(table.trailing());
+}
+
+fn print_function(
+    result: &mut String,
+    generics: &Option<Arc<str>>,
+    args: &[(TypedName, Option<Expression>)],
+    returns: &Option<Arc<str>>,
+    body: &CodeBlock,
+) {
+    if let Some(generics) = generics {
+        result.push_str(generics);
+    }
+    push(result, "(");
+    print_list(result, args, &mut |result, (arg, _)| {
+        print_typed_name(result, arg)
+    });
+    result.push(')');
+    if let Some(returns) = returns {
+        result.push_str(": ");
+        result.push_str(returns);
+    }
+    // Lua has no default parameters, they are lowered back into nil checks
+    for (arg, default) in args {
+        if let Some(default) = default {
+            push_keyword(result, "if ");
+            push(result, &arg.name);
+            result.push_str(" == nil then ");
+            push(result, &arg.name);
+            result.push_str(" = ");
+            print_expression(result, default);
+            trim_end(result);
+            push_keyword(result, "end");
+        }
+    }
+    print_code_block(result, body);
+}
+
+fn print_if_expression(result: &mut String, ctoken: &ComplexToken, keyword: &str) {
+    let ComplexTokenKind::Ternary {
+        condition,
+        then,
+        otherwise,
+    } = ctoken.token()
+    else {
+        unreachable!()
+    };
+
+    result.push_str(ctoken.leading());
+    push_keyword(result, keyword);
+    push_trivia_or_space(result, ctoken.trailing());
+    print_expression(result, condition);
+    push_keyword(result, "then ");
+    print_expression(result, then);
+
+    match otherwise.iter().next() {
+        Some(elseif)
+            if otherwise.len() == 1
+                && matches!(elseif.token(), ComplexTokenKind::Ternary { .. }) =>
+        {
+            print_if_expression(result, elseif, "elseif")
+        }
+        _ => {
+            push_keyword(result, "else ");
+            print_expression(result, otherwise);
+        }
+    }
+}
+
+fn print_expression(result: &mut String, expr: &Expression) {
+    use ComplexTokenKind::*;
+
+    for ctoken in expr {
+        match ctoken.token() {
+            Symbol(lexeme) | MultilineString(lexeme) | Operator((lexeme, _)) => {
+                print_token(result, ctoken, lexeme)
+            }
+            Number(number) => print_token(result, ctoken, &number.to_string()),
+            Ident(_) => print_identifier(result, ctoken),
+            Call(args) => print_call(result, ctoken, args),
+            Table(data) => print_table(result, ctoken, data),
+            Expr(expr) => {
+                result.push_str(ctoken.leading());
+                push(result, "(");
+                result.push_str(ctoken.trailing());
+                print_expression(result, expr);
+                trim_end(result);
+                result.push(')');
+            }
+            Lambda {
+                generics,
+                args,
+                returns,
+                body,
+            } => {
+                print_token(result, ctoken, "function");
+                print_function(result, generics, args, returns, body);
+                push_keyword(result, "end");
+            }
+            Ternary { .. } => print_if_expression(result, ctoken, "if"),
+            InterpolatedString { parts, values } => {
+                result.push_str(ctoken.leading());
+                push(result, "`");
+                for (i, part) in parts.iter().enumerate() {
+                    result.push_str(part);
+                    if let Some(value) = values.get(i) {
+                        result.push('{');
+                        print_expression(result, value);
+                        result.push('}');
+                    }
+                }
+                result.push('`');
+                result.push_str(ctoken.trailing());
+            }
+            TypeCast(ty) => {
+                print_token(result, ctoken, "::");
+                push(result, ty);
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+fn print_code_block(result: &mut String, block: &CodeBlock) {
+    result.push_str(&block.leading);
+    print_statements(result, &block.code);
+}
+
+fn print_end(result: &mut String, ctoken: &ComplexToken) {
+    result.push_str(ctoken.leading());
+    push_keyword(result, "end");
+    result.push_str(ctoken.trailing());
+}
+
+fn print_statements(result: &mut String, tree: &Expression) {
+    use ComplexTokenKind::*;
+
+    let mut tree = tree.iter().enumerate().peekable();
+
+    while let Some((i, ctoken)) = tree.next() {
+        if i != 0 {
+            if !result.ends_with(char::is_whitespace) {
+                result.push(';');
+                result.push(' ');
+            } else if matches!(ctoken.token(), Expr(_)) {
+                result.push(';');
+            }
+        }
+        result.push_str(ctoken.prefix());
+
+        match ctoken.token() {
+            Variable { names, values } => {
+                push(result, "local ");
+                print_list(result, names, &mut |result, (name, close)| {
+                    print_typed_name(result, name);
+                    if *close {
+                        result.push_str(" <close>");
+                    }
+                });
+                if !values.is_empty() {
+                    push_keyword(result, "= ");
+                    print_expressions(result, values);
+                }
+                push_trailing(result, ctoken.trailing());
+            }
+            Alter { names, values } => {
+                print_list(result, names, &mut print_identifier);
+                push_keyword(result, "=");
+                push_trivia_or_space(result, ctoken.trailing());
+                print_expressions(result, values);
+            }
+            CompoundAlter {
+                name,
+                operator,
+                value,
+            } => {
+                print_identifier(result, name);
+                push_keyword(result, operator);
+                push_trivia_or_space(result, ctoken.trailing());
+                print_expression(result, value);
+            }
+            Function {
+                local,
+                name,
+                generics,
+                args,
+                returns,
+                body,
+            } => {
+                if *local {
+                    push(result, "local ");
+                }
+                push(result, "function ");
+                print_expression(result, name);
+                print_function(result, generics, args, returns, body);
+                print_end(result, ctoken);
+            }
+            Method {
+                name,
+                generics,
+                args,
+                returns,
+                body,
+            } => {
+                let mut name = name.clone();
+                let i = name.len() - 2;
+                name[i] = ComplexToken::new(
+                    Symbol(":".into()),
+                    name[i].leading(),
+                    name[i].trailing(),
+                    name[i].position(),
+                );
+
+                push(result, "function ");
+                print_expression(result, &name);
+                print_function(result, generics, &args[1..], returns, body);
+                print_end(result, ctoken);
+            }
+            IfStatement {
+                condition,
+                body,
+                next,
+            } => {
+                push(result, "if ");
+                print_expression(result, condition);
+                push_keyword(result, "then");
+                print_code_block(result, body);
+
+                let mut next = next;
+                while let Some(branch) = next {
+                    let IfStatement {
+                        condition,
+                        body,
+                        next: branch_next,
+                    } = branch.token()
+                    else {
+                        unreachable!()
+                    };
+
+                    if condition.is_empty() {
+                        push_keyword(result, "else");
+                    } else {
+                        push_keyword(result, "elseif ");
+                        print_expression(result, condition);
+                        push_keyword(result, "then");
+                    }
+                    print_code_block(result, body);
+                    next = branch_next;
+                }
+                print_end(result, ctoken);
+            }
+            WhileLoop { condition, body } => {
+                push(result, "while ");
+                print_expression(result, condition);
+                push_keyword(result, "do");
+                print_code_block(result, body);
+                print_end(result, ctoken);
+            }
+            ForLoop {
+                iter,
+                start,
+                end,
+                step,
+                code,
+            } => {
+                push(result, "for ");
+                print_typed_name(result, iter);
+                push_keyword(result, "= ");
+                print_expression(result, start);
+                result.push_str(", ");
+                print_expression(result, end);
+                if let Some(step) = step {
+                    result.push_str(", ");
+                    print_expression(result, step);
+                }
+                push_keyword(result, "do");
+                print_code_block(result, code);
+                print_end(result, ctoken);
+            }
+            ForFuncLoop {
+                iters,
+                expr,
+                stop,
+                initial,
+                closing,
+                code,
+            } => {
+                push(result, "for ");
+                print_list(result, iters, &mut print_typed_name);
+                push_keyword(result, "in ");
+                print_expression(result, expr);
+                for expr in [stop, initial, closing].into_iter().flatten() {
+                    result.push_str(", ");
+                    print_expression(result, expr);
+                }
+                push_keyword(result, "do");
+                print_code_block(result, code);
+                print_end(result, ctoken);
+            }
+            RepeatLoop { condition, body } => {
+                push(result, "repeat");
+                print_code_block(result, body);
+                push_keyword(result, "until ");
+                print_expression(result, condition);
+                push_trailing(result, ctoken.trailing());
+            }
+            Ident(_) => print_identifier(result, ctoken),
+            Expr(expr) => {
+                push(result, "(");
+                print_expression(result, expr);
+                trim_end(result);
+                result.push(')');
+                if let Some((_, call)) = tree.next() {
+                    print_identifier(result, call);
+                }
+            }
+            DoBlock(body) => {
+                result.push_str(ctoken.leading());
+                push(result, "do");
+                print_code_block(result, body);
+                push_keyword(result, "end");
+                result.push_str(ctoken.trailing());
+            }
+            Return(exprs) => {
+                push(result, "return");
+                if let Some(exprs) = exprs {
+                    result.push(' ');
+                    print_expressions(result, exprs);
+                }
+                push_trailing(result, ctoken.trailing());
+            }
+            Break => print_token(result, ctoken, "break"),
+            Continue => print_token(result, ctoken, "continue"),
+            TypeAlias {
+                export,
+                name,
+                generics,
+                value,
+            } => {
+                if *export {
+                    push(result, "export ");
+                }
+                push(result, "type ");
+                push(result, name);
+                if let Some(generics) = generics {
+                    result.push_str(generics);
+                }
+                result.push_str(" = ");
+                result.push_str(value);
+                push_trailing(result, ctoken.trailing());
+            }
+            // the fields of an enum are used as names, each one becomes a local
+            Enum(table) => {
+                let Some(Table(data)) = table.front().map(ComplexToken::token) else {
+                    unreachable!()
+                };
+
+                push(result, "local ");
+                print_list(result, data, &mut |result, (key, _)| {
+                    print_expression(result, key.as_ref().unwrap());
+                    trim_end(result);
+                });
+                push_keyword(result, "= ");
+                print_list(result, data, &mut |result, (_, value)| {
+                    print_expression(result, value);
+                    trim_end(result);
+                });
+                push_trailing(result, ctoken.trailing());
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+pub fn print_ast(tree: &Expression) -> String {
+    let mut result = String::new();
+    print_statements(&mut result, tree);
+    result
+}
+
+pub(crate) fn print_expr(expr: &Expression) -> String {
+    let mut result = String::new();
+    print_expression(&mut result, expr);
+    result
+}
@@ -1,14 +1,19 @@
 use clap::Parser;
 use cluna::{
-    compiler::compile_ast,
-    error::{get_files, Diagnostic, DiagnosticLevel},
-    lexer::scan_code,
-    parser::parse_tokens,
+    compiler::CompilerOptions,
+    convert,
+    error::{Diagnostic, DiagnosticLevel, SourceDb},
+    lexer::Dialect,
+    ConvertOptions,
 };
 use colored::Colorize;
 use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 use std::{
-    path::PathBuf,
+    collections::HashMap,
+    fmt::Write,
+    path::{Path, PathBuf},
+    sync::Mutex,
     time::{Duration, Instant},
 };
 
@@ -25,25 +30,289 @@ struct Cli {
     /// Supress debug output.
     #[clap(short, long)]
     quiet: bool,
+    /// Dialect of the input code.
+    #[clap(long, value_enum, default_value_t)]
+    dialect: Dialect,
+    /// Keep type annotations as comments instead of dropping them.
+    #[clap(long)]
+    preserve_types: bool,
+    /// Rewrite `x = x + y` into `x += y` when `x` has no side effects.
+    #[clap(long)]
+    compound_assignment: bool,
+    /// Turn `a and b or c` into `a ? b : c` when `b` can never be falsy.
+    #[clap(long)]
+    ternary: bool,
+    /// Turn nil guards like `a and a.b` into `a?.b` where only their truthiness matters.
+    #[clap(long)]
+    safe_navigation: bool,
+    /// Turn if chains comparing one value to constants into `match` statements.
+    #[clap(long)]
+    match_statements: bool,
+    /// Turn local tables of integer constants that are never modified into enums.
+    #[clap(long)]
+    enums: bool,
+    /// Turn `repeat ... until true` loop bodies that emulate `continue` into real `continue`s.
+    #[clap(long)]
+    continue_statements: bool,
+    /// Turn `if x == nil then x = default end` at the start of a function into a default parameter value.
+    #[clap(long)]
+    default_parameters: bool,
+    /// Convert every file of a directory, even the ones whose output is up to date.
+    #[clap(long)]
+    force: bool,
+    /// Remove the outputs of deleted files when compiling a directory.
+    #[clap(long)]
+    clean: bool,
 }
 
-fn compile_file(path: &PathBuf, output: Option<PathBuf>, quiet: bool) -> Result<(), Diagnostic> {
+// the cache of a directory lives in this folder inside it
+const CACHE_DIR: &str = ".cluna-cache";
+
+#[derive(Debug, Clone, PartialEq)]
+struct CacheEntry {
+    // hash of the input, the version and the options
+    key: String,
+    // hash of the output that was written, to notice outputs changed by hand
+    output: String,
+}
+
+type Cache = HashMap<String, CacheEntry>;
+
+// the cache outlives the binary that wrote it, so the hash must not change between builds
+fn hash(parts: &[&[u8]]) -> String {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        // the lengths keep the parts from running into each other
+        hasher.update((part.len() as u64).to_le_bytes());
+        hasher.update(part);
+    }
+    hasher
+        .finalize()
+        .iter()
+        .fold(String::new(), |mut hex, byte| {
+            write!(hex, "{byte:02x}").unwrap();
+            hex
+        })
+}
+
+// spelled out instead of using the Debug output, which makes no promise to stay the same
+fn options_key(dialect: Dialect, options: &CompilerOptions) -> String {
+    let CompilerOptions {
+        preserve_types,
+        compound_assignment,
+        ternary,
+        safe_navigation,
+        match_statements,
+        enums,
+        continue_statements,
+        default_parameters,
+    } = *options;
+    let dialect = match dialect {
+        Dialect::Lua => "lua",
+        Dialect::Luau => "luau",
+        Dialect::GLua => "glua",
+    };
+    format!(
+        "dialect={dialect} preserve_types={preserve_types} \
+         compound_assignment={compound_assignment} ternary={ternary} \
+         safe_navigation={safe_navigation} match_statements={match_statements} enums={enums} \
+         continue_statements={continue_statements} default_parameters={default_parameters}"
+    )
+}
+
+fn cache_key(code: &str, dialect: Dialect, options: &CompilerOptions) -> String {
+    hash(&[
+        env!("CARGO_PKG_VERSION").as_bytes(),
+        options_key(dialect, options).as_bytes(),
+        code.as_bytes(),
+    ])
+}
+
+// a missing or unreadable cache only means that every file gets converted again
+fn load_cache(root: &Path) -> Cache {
+    let manifest =
+        std::fs::read_to_string(root.join(CACHE_DIR).join("manifest")).unwrap_or_default();
+    manifest
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ' ');
+            let key = parts.next()?.to_owned();
+            let output = parts.next()?.to_owned();
+            Some((parts.next()?.to_owned(), CacheEntry { key, output }))
+        })
+        .collect()
+}
+
+fn save_cache(root: &Path, cache: &Cache) -> Result<(), String> {
+    let dir = root.join(CACHE_DIR);
+    std::fs::create_dir_all(&dir).map_err(io_error)?;
+    let mut entries = cache.iter().collect::<Vec<_>>();
+    entries.sort_by_key(|(input, _)| *input);
+    let manifest = entries
+        .into_iter()
+        .map(|(input, entry)| format!("{} {} {input}\n", entry.key, entry.output))
+        .collect::<String>();
+    std::fs::write(dir.join("manifest.tmp"), manifest).map_err(io_error)?;
+    std::fs::rename(dir.join("manifest.tmp"), dir.join("manifest")).map_err(io_error)
+}
+
+// errors are rendered here, where the source they point into is still around
+fn compile_file(
+    path: &Path,
+    code: String,
+    output: Option<PathBuf>,
+    dialect: Dialect,
+    options: &CompilerOptions,
+    quiet: bool,
+) -> Result<String, String> {
     if !quiet {
         eprintln!("   {} {}", "Compiling".green().bold(), path.display());
     }
-    let code = std::fs::read_to_string(path).map_err(|e| Diagnostic::other(e.to_string()))?;
-    {
-        let files = get_files();
-        let mut files = files.lock().unwrap();
-        files.insert(path.display().to_string(), code.clone());
-    }
-    let scanned = scan_code(code, Some(path.display().to_string()))?;
-    let parsed = parse_tokens(&scanned, Some(path.display().to_string()))?;
-    let compiled = compile_ast(parsed);
+    let converted = convert(
+        &code,
+        &ConvertOptions {
+            path: Some(path.display().to_string()),
+            dialect,
+            compiler: options.clone(),
+            ..Default::default()
+        },
+    );
+    let mut sources = SourceDb::new();
+    sources.insert(path.display().to_string(), code);
+    let mut diagnostics = converted
+        .diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.render(&sources));
+    let Some(compiled) = converted.code else {
+        let error = diagnostics.next_back().unwrap();
+        eprint!(
+            "{}",
+            diagnostics
+                .map(|warning| warning + "\n")
+                .collect::<String>()
+        );
+        return Err(error);
+    };
+    eprint!(
+        "{}",
+        diagnostics
+            .map(|warning| warning + "\n")
+            .collect::<String>()
+    );
     let output = output.unwrap_or_else(|| path.with_extension("clue"));
-    std::fs::create_dir_all(output.parent().unwrap())
-        .map_err(|e| Diagnostic::other(e.to_string()))?;
-    std::fs::write(output, compiled).map_err(|e| Diagnostic::other(e.to_string()))
+    std::fs::create_dir_all(output.parent().unwrap()).map_err(io_error)?;
+    std::fs::write(output, &compiled).map_err(io_error)?;
+    Ok(compiled)
+}
+
+fn warn(message: String) {
+    eprintln!(
+        "{}",
+        Diagnostic::other(message).level(DiagnosticLevel::Warning)
+    );
+}
+
+// returns how many files were converted and how many were already up to date
+fn compile_dir(
+    root: &Path,
+    dialect: Dialect,
+    options: &CompilerOptions,
+    quiet: bool,
+    force: bool,
+    clean: bool,
+) -> Result<(usize, usize), String> {
+    let mut stack = vec![root.to_path_buf()];
+    let files = std::iter::from_fn(move || {
+        while let Some(path) = stack.pop() {
+            if path.is_dir() {
+                stack.extend(path.read_dir().unwrap().map(|entry| entry.unwrap().path()));
+            } else if path.is_file()
+                && path
+                    .extension()
+                    .is_some_and(|ext| ext == "lua" || (dialect == Dialect::Luau && ext == "luau"))
+            {
+                return Some(path);
+            }
+        }
+
+        None
+    });
+
+    let previous = load_cache(root);
+    let cache = Mutex::new(Cache::new());
+    let fresh = Mutex::new(0);
+    let converted = Mutex::new(0);
+    let result: Result<(), String> = files.par_bridge().try_for_each(|path| {
+        let input = path.strip_prefix(root).unwrap().display().to_string();
+        let code = std::fs::read_to_string(&path).map_err(io_error)?;
+        let key = cache_key(&code, dialect, options);
+        let up_to_date = previous.get(&input).filter(|entry| {
+            entry.key == key
+                && std::fs::read(path.with_extension("clue"))
+                    .is_ok_and(|output| hash(&[&output]) == entry.output)
+        });
+        let entry = match up_to_date {
+            Some(entry) if !force => {
+                *fresh.lock().unwrap() += 1;
+                entry.clone()
+            }
+            _ => {
+                let compiled = compile_file(&path, code, None, dialect, options, quiet)?;
+                *converted.lock().unwrap() += 1;
+                CacheEntry {
+                    key,
+                    output: hash(&[compiled.as_bytes()]),
+                }
+            }
+        };
+        cache.lock().unwrap().insert(input, entry);
+        Ok(())
+    });
+
+    // files an error stopped us from reaching keep their entries, and the outputs of deleted
+    // files are kept around until they are cleaned
+    let mut cache = cache.into_inner().unwrap();
+    for (input, entry) in previous {
+        if cache.contains_key(&input) {
+            continue;
+        }
+        if root.join(&input).exists() {
+            cache.insert(input, entry);
+            continue;
+        }
+        let output = root.join(&input).with_extension("clue");
+        let Ok(written) = std::fs::read(&output) else {
+            continue;
+        };
+        if clean && hash(&[&written]) == entry.output {
+            std::fs::remove_file(&output).map_err(io_error)?;
+            if !quiet {
+                eprintln!("    {} {}", "Removed".green().bold(), output.display());
+            }
+        } else {
+            if clean {
+                warn(format!(
+                    "{} was modified after it was written, it was not removed",
+                    output.display()
+                ));
+            } else {
+                warn(format!(
+                    "{} is stale, {} was deleted. Use --clean to remove it",
+                    output.display(),
+                    root.join(&input).display()
+                ));
+            }
+            cache.insert(input, entry);
+        }
+    }
+    save_cache(root, &cache)?;
+
+    result?;
+    Ok((converted.into_inner().unwrap(), fresh.into_inner().unwrap()))
+}
+
+fn io_error(error: std::io::Error) -> String {
+    Diagnostic::other(error.to_string()).to_string()
 }
 
 fn format_duration(duration: Duration) -> String {
@@ -67,16 +336,24 @@ fn format_duration(duration: Duration) -> String {
     }
 }
 
-fn compile() -> Result<(), Diagnostic> {
+fn compile() -> Result<(), String> {
     let args = Cli::parse();
     let path = args.path;
     if !path.exists() {
-        return Err(Diagnostic::other(format!(
-            "The path {:?} does not exist",
-            path
-        )));
+        return Err(Diagnostic::other(format!("The path {:?} does not exist", path)).to_string());
     }
 
+    let options = CompilerOptions {
+        preserve_types: args.preserve_types,
+        compound_assignment: args.compound_assignment,
+        ternary: args.ternary,
+        safe_navigation: args.safe_navigation,
+        match_statements: args.match_statements,
+        enums: args.enums,
+        continue_statements: args.continue_statements,
+        default_parameters: args.default_parameters,
+    };
+
     let start = Instant::now();
     if path.is_file() {
         let output = args.output.or_else(|| {
@@ -86,33 +363,29 @@ fn compile() -> Result<(), Diagnostic> {
                 out_dir
             })
         });
-        compile_file(&path, output, args.quiet)?;
+        let code = std::fs::read_to_string(&path).map_err(io_error)?;
+        compile_file(&path, code, output, args.dialect, &options, args.quiet)?;
     } else if path.is_dir() {
         if args.output.is_some() {
+            warn("output flag is ignored when compiling a directory".to_owned());
+        }
+
+        let (_, fresh) = compile_dir(
+            &path,
+            args.dialect,
+            &options,
+            args.quiet,
+            args.force,
+            args.clean,
+        )?;
+        if !args.quiet && fresh > 0 {
             eprintln!(
-                "{}",
-                Diagnostic::other("output flag is ignored when compiling a directory".to_owned())
-                    .level(DiagnosticLevel::Warning)
+                "       {} {} file{} up to date",
+                "Fresh".green().bold(),
+                fresh,
+                if fresh == 1 { " was" } else { "s were" }
             );
         }
-
-        let mut stack = vec![path.clone()];
-
-        let files = std::iter::from_fn(move || {
-            while let Some(path) = stack.pop() {
-                if path.is_dir() {
-                    stack.extend(path.read_dir().unwrap().map(|entry| entry.unwrap().path()));
-                } else if path.is_file() && path.extension().map_or(false, |ext| ext == "lua") {
-                    return Some(path);
-                }
-            }
-
-            None
-        });
-
-        files
-            .par_bridge()
-            .try_for_each(|path| compile_file(&path, None, args.quiet))?;
     }
 
     if !args.quiet {
@@ -136,9 +409,18 @@ fn main() {
 
 #[cfg(test)]
 mod test {
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
-    use cluna::{compiler::compile_ast, error::Diagnostic, lexer::scan_code, parser::parse_tokens};
+    use cluna::{
+        compiler::{compile_ast, CompilerOptions},
+        error::Diagnostic,
+        lexer::{scan_code, Dialect},
+        lua_printer::print_ast,
+        parser::{
+            parse_tokens, walk_token, walk_token_mut, ComplexToken, ComplexTokenKind, Expression,
+            Fold, Visitor, VisitorMut,
+        },
+    };
     use tests_proc_macro::gen_tests;
 
     macro_rules! settings {
@@ -161,9 +443,22 @@ mod test {
         }};
     }
 
+    fn dialect(path: &Path) -> Dialect {
+        match path.parent().and_then(|dir| dir.file_name()) {
+            Some(dir) if dir == "luau" => Dialect::Luau,
+            Some(dir) if dir == "glua" => Dialect::GLua,
+            _ => Dialect::Lua,
+        }
+    }
+
     fn scan(path: PathBuf) -> Result<(), Diagnostic> {
         let code = std::fs::read_to_string(&path).unwrap();
-        let scanned = scan_code(code, Some(path.display().to_string()))?;
+        let scanned = scan_code(
+            &code,
+            Some(path.display().to_string()),
+            dialect(&path),
+            &mut Vec::new(),
+        )?;
         let settings = settings!("lexer", path);
 
         settings.bind(|| {
@@ -175,8 +470,13 @@ mod test {
 
     fn parse(path: PathBuf) -> Result<(), Diagnostic> {
         let code = std::fs::read_to_string(&path).unwrap();
-        let scanned = scan_code(code, Some(path.display().to_string()))?;
-        let parsed = parse_tokens(&scanned, Some(path.display().to_string()))?;
+        let scanned = scan_code(
+            &code,
+            Some(path.display().to_string()),
+            dialect(&path),
+            &mut Vec::new(),
+        )?;
+        let parsed = parse_tokens(&scanned, Some(path.display().to_string()), &mut Vec::new())?;
         let settings = settings!("parser", path);
 
         settings.bind(|| {
@@ -186,11 +486,17 @@ mod test {
         Ok(())
     }
 
+    #[allow(deprecated)]
     fn compile(path: PathBuf) -> Result<(), Diagnostic> {
         let code = std::fs::read_to_string(&path).unwrap();
-        let scanned = scan_code(code, Some(path.display().to_string()))?;
-        let parsed = parse_tokens(&scanned, Some(path.display().to_string()))?;
-        let compiled = compile_ast(parsed);
+        let scanned = scan_code(
+            &code,
+            Some(path.display().to_string()),
+            dialect(&path),
+            &mut Vec::new(),
+        )?;
+        let parsed = parse_tokens(&scanned, Some(path.display().to_string()), &mut Vec::new())?;
+        let compiled = compile_ast(parsed, None, &CompilerOptions::default(), &mut Vec::new());
         let settings = settings!("compiler", path);
 
         settings.bind(|| {
@@ -200,12 +506,297 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn trees_can_cross_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Expression>();
+    }
+
+    // trivia, positions and token indices can't survive printing, the rest of the tree must
+    fn structure(tree: &Expression) -> String {
+        // the indentation of the position being skipped
+        let mut position = None;
+        let mut is_trivia = |line: &&str| {
+            let indent = line.len() - line.trim_start().len();
+            if let Some(start) = position {
+                if indent == start {
+                    position = None;
+                }
+                return true;
+            }
+            let (field, value) = line.trim().split_once(": ").unwrap_or_default();
+            match field {
+                "prefix" | "leading" | "trailing" => true,
+                "position" => {
+                    position = Some(indent);
+                    true
+                }
+                "start" | "end" => value.trim_end_matches(',').parse::<usize>().is_ok(),
+                _ => false,
+            }
+        };
+
+        format!("{tree:#?}")
+            .lines()
+            .filter(|line| !is_trivia(line))
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    fn round_trip(path: PathBuf) -> Result<(), Diagnostic> {
+        let code = std::fs::read_to_string(&path).unwrap();
+        let scanned = scan_code(
+            &code,
+            Some(path.display().to_string()),
+            dialect(&path),
+            &mut Vec::new(),
+        )?;
+        let parsed = parse_tokens(&scanned, Some(path.display().to_string()), &mut Vec::new())?;
+        let printed = print_ast(&parsed);
+        let rescanned = scan_code(&printed, None, dialect(&path), &mut Vec::new())?;
+        let reparsed = parse_tokens(&rescanned, None, &mut Vec::new())?;
+
+        assert_eq!(structure(&parsed), structure(&reparsed));
+
+        Ok(())
+    }
+
+    #[test]
+    fn printer_keeps_comments() -> Result<(), Diagnostic> {
+        for code in [
+            "-- header\nlocal a = 1 -- trailing\n--[[ block ]] print(a)",
+            "#!/usr/bin/env lua\n--[==[ long\ncomment ]==]\nlocal function f(a, b) -- args\n\t-- body\n\tif a then --[[ then ]] return b end\n\twhile a do\n\t\t-- nothing\n\tend\nend\n-- end of file\n",
+        ] {
+            let scanned = scan_code(code, None, Dialect::Lua, &mut Vec::new())?;
+            let parsed = parse_tokens(&scanned, None, &mut Vec::new())?;
+            assert_eq!(print_ast(&parsed), code);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn printer_lowers_clue_only_tokens() -> Result<(), Diagnostic> {
+        let code = "local Color = {RED = 1, GREEN = 2}\nlocal f = function(a, b) return a end";
+        let scanned = scan_code(code, None, Dialect::Lua, &mut Vec::new())?;
+        let mut parsed = parse_tokens(&scanned, None, &mut Vec::new())?;
+        let mut value = |i: usize| {
+            let ComplexTokenKind::Variable { values, .. } = parsed[i].token_mut() else {
+                unreachable!()
+            };
+            values.pop().unwrap()
+        };
+
+        let table = value(0);
+        let mut lambda = value(1);
+        let ComplexTokenKind::Lambda { args, .. } = lambda[0].token_mut() else {
+            unreachable!()
+        };
+        args[1].1 = Some(table.clone());
+        let ComplexTokenKind::Variable { values, .. } = parsed[1].token_mut() else {
+            unreachable!()
+        };
+        values.push(lambda);
+        *parsed[0].token_mut() = ComplexTokenKind::Enum(table);
+
+        assert_eq!(
+            print_ast(&parsed),
+            "local RED, GREEN = 1, 2\nlocal f = function(a, b) if b == nil then b = {RED = 1, GREEN = 2} end return a end"
+        );
+
+        Ok(())
+    }
+
+    #[derive(Default)]
+    struct Counter(usize);
+
+    impl Visitor for Counter {
+        fn visit_token(&mut self, ctoken: &ComplexToken) {
+            self.0 += 1;
+            walk_token(self, ctoken);
+        }
+    }
+
+    impl VisitorMut for Counter {
+        fn visit_token(&mut self, ctoken: &mut ComplexToken) {
+            self.0 += 1;
+            walk_token_mut(self, ctoken);
+        }
+    }
+
+    struct Identity;
+
+    impl Fold for Identity {}
+
+    // the default walks must reach the same tokens and rebuild the same tree
+    fn visit(path: PathBuf) -> Result<(), Diagnostic> {
+        let code = std::fs::read_to_string(&path).unwrap();
+        let scanned = scan_code(&code, None, dialect(&path), &mut Vec::new())?;
+        let parsed = parse_tokens(&scanned, None, &mut Vec::new())?;
+
+        let mut counter = Counter::default();
+        Visitor::visit_expression(&mut counter, &parsed);
+        let mut folded = Identity.fold_expression(parsed.clone());
+        assert_eq!(folded, parsed);
+        let mut mut_counter = Counter::default();
+        VisitorMut::visit_expression(&mut mut_counter, &mut folded);
+        assert_eq!(counter.0, mut_counter.0);
+
+        Ok(())
+    }
+
+    // marking the statements for the source map must not change the code
+    fn source_map(path: PathBuf) -> Result<(), Diagnostic> {
+        let code = std::fs::read_to_string(&path).unwrap();
+        let mut options = cluna::ConvertOptions {
+            dialect: dialect(&path),
+            compiler: CompilerOptions {
+                compound_assignment: true,
+                ternary: true,
+                safe_navigation: true,
+                match_statements: true,
+                enums: true,
+                continue_statements: true,
+                default_parameters: true,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let plain = cluna::convert(&code, &options);
+        options.source_map = true;
+        let mapped = cluna::convert(&code, &options);
+
+        assert_eq!(plain.source_map, None);
+        assert_eq!(mapped.code, plain.code);
+        if let (Some(compiled), Some(lines)) = (&mapped.code, &mapped.source_map) {
+            assert_eq!(lines.len(), compiled.split('\n').count());
+            assert!(lines
+                .iter()
+                .all(|&line| (1..=code.lines().count().max(1)).contains(&line)));
+        }
+
+        Ok(())
+    }
+
+    // tokens read back from JSON must be the scanned ones, and a tree read back from JSON must
+    // compile exactly like the parsed one
+    #[cfg(feature = "serde")]
+    fn serialize(path: PathBuf) -> Result<(), Diagnostic> {
+        let code = std::fs::read_to_string(&path).unwrap();
+        let scanned = scan_code(&code, None, dialect(&path), &mut Vec::new())?;
+        let json = serde_json::to_string(&scanned).unwrap();
+        let tokens: Vec<cluna::lexer::Token> = serde_json::from_str(&json).unwrap();
+        assert_eq!(tokens, scanned);
+        let parsed = parse_tokens(&scanned, None, &mut Vec::new())?;
+
+        let json = serde_json::to_string(&parsed).unwrap();
+        let deserialized: Expression = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, parsed);
+
+        let options = CompilerOptions::default();
+        assert_eq!(
+            compile_ast(deserialized, None, &options, &mut Vec::new()),
+            compile_ast(parsed, None, &options, &mut Vec::new())
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn source_map_follows_statements() {
+        let code =
+            "local a = 1\n\nif a then\n    print(a)\nend\nlocal function f()\n    return a\nend";
+        let options = cluna::ConvertOptions {
+            source_map: true,
+            ..Default::default()
+        };
+        let converted = cluna::convert(code, &options);
+
+        assert_eq!(
+            converted.code.unwrap(),
+            "local a = 1\n\n\n\nif a   {print(a);\n}\nlocal fn f() {return a\n\n\n}"
+        );
+        assert_eq!(
+            converted.source_map,
+            Some(vec![1, 1, 1, 1, 3, 4, 6, 7, 7, 7])
+        );
+    }
+
+    #[test]
+    fn cache_skips_up_to_date_files() {
+        let root = std::env::temp_dir().join(format!("cluna-cache-{}", std::process::id()));
+        std::fs::create_dir_all(&root).unwrap();
+        let input = root.join("a.lua");
+        let output = root.join("a.clue");
+        let options = CompilerOptions::default();
+        let compile = |force, clean| {
+            super::compile_dir(&root, Dialect::Lua, &options, true, force, clean).unwrap()
+        };
+
+        std::fs::write(&input, "local a = 1").unwrap();
+        assert_eq!(compile(false, false), (1, 0));
+        assert_eq!(compile(false, false), (0, 1));
+        assert_eq!(compile(true, false), (1, 0));
+
+        std::fs::write(&input, "local a = 2").unwrap();
+        assert_eq!(compile(false, false), (1, 0));
+        std::fs::write(&output, "edited").unwrap();
+        assert_eq!(compile(false, false), (1, 0));
+
+        std::fs::remove_file(&input).unwrap();
+        assert_eq!(compile(false, false), (0, 0));
+        assert!(output.exists());
+        assert_eq!(compile(false, true), (0, 0));
+        assert!(!output.exists());
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn cache_hash_is_stable() {
+        // manifests written by older builds must keep matching
+        assert_eq!(
+            super::hash(&[b"local a = 1"]),
+            "af665a374ad2fbaa016b14518458844305f0ca6202df743156cfe02562556ad8"
+        );
+    }
+
+    #[test]
+    fn visitors_rewrite_nested_code() {
+        struct Rename;
+
+        impl VisitorMut for Rename {
+            fn visit_token(&mut self, ctoken: &mut ComplexToken) {
+                if let ComplexTokenKind::Symbol(symbol) = ctoken.token_mut() {
+                    if symbol.as_ref() == "print" {
+                        *symbol = "log".into();
+                    }
+                }
+                walk_token_mut(self, ctoken);
+            }
+        }
+
+        let code = "local t = {f = function(x) if x then print(x) end end}";
+        let scanned = scan_code(code, None, Dialect::Lua, &mut Vec::new()).unwrap();
+        let mut parsed = parse_tokens(&scanned, None, &mut Vec::new()).unwrap();
+        Rename.visit_expression(&mut parsed);
+        let compiled = compile_ast(parsed, None, &CompilerOptions::default(), &mut Vec::new());
+
+        assert!(compiled.contains("log(x)"));
+        assert!(!compiled.contains("print"));
+    }
+
     mod lua5_1 {
         use super::*;
 
         gen_tests!("test-data/lua5.1-tests", scan);
         gen_tests!("test-data/lua5.1-tests", parse);
         gen_tests!("test-data/lua5.1-tests", compile);
+        gen_tests!("test-data/lua5.1-tests", round_trip);
+        gen_tests!("test-data/lua5.1-tests", source_map);
+        gen_tests!("test-data/lua5.1-tests", visit);
+        #[cfg(feature = "serde")]
+        gen_tests!("test-data/lua5.1-tests", serialize);
     }
 
     mod lua5_2 {
@@ -214,6 +805,11 @@ mod test {
         gen_tests!("test-data/lua5.2-tests", scan);
         gen_tests!("test-data/lua5.2-tests", parse);
         gen_tests!("test-data/lua5.2-tests", compile);
+        gen_tests!("test-data/lua5.2-tests", round_trip);
+        gen_tests!("test-data/lua5.2-tests", source_map);
+        gen_tests!("test-data/lua5.2-tests", visit);
+        #[cfg(feature = "serde")]
+        gen_tests!("test-data/lua5.2-tests", serialize);
     }
 
     mod lua5_3 {
@@ -222,6 +818,11 @@ mod test {
         gen_tests!("test-data/lua5.3-tests", scan);
         gen_tests!("test-data/lua5.3-tests", parse);
         gen_tests!("test-data/lua5.3-tests", compile);
+        gen_tests!("test-data/lua5.3-tests", round_trip);
+        gen_tests!("test-data/lua5.3-tests", source_map);
+        gen_tests!("test-data/lua5.3-tests", visit);
+        #[cfg(feature = "serde")]
+        gen_tests!("test-data/lua5.3-tests", serialize);
     }
 
     mod lua5_4 {
@@ -230,6 +831,11 @@ mod test {
         gen_tests!("test-data/lua5.4-tests", scan);
         gen_tests!("test-data/lua5.4-tests", parse);
         gen_tests!("test-data/lua5.4-tests", compile);
+        gen_tests!("test-data/lua5.4-tests", round_trip);
+        gen_tests!("test-data/lua5.4-tests", source_map);
+        gen_tests!("test-data/lua5.4-tests", visit);
+        #[cfg(feature = "serde")]
+        gen_tests!("test-data/lua5.4-tests", serialize);
     }
 
     mod extra {
@@ -237,23 +843,230 @@ mod test {
         gen_tests!("test-data/extra", scan);
         gen_tests!("test-data/extra", parse);
         gen_tests!("test-data/extra", compile);
+        gen_tests!("test-data/extra", round_trip);
+        gen_tests!("test-data/extra", source_map);
+        gen_tests!("test-data/extra", visit);
+        #[cfg(feature = "serde")]
+        gen_tests!("test-data/extra", serialize);
     }
 
-    mod negative {
+    mod luau {
+        use super::*;
+
+        fn compile_typed(path: PathBuf) -> Result<(), Diagnostic> {
+            let code = std::fs::read_to_string(&path).unwrap();
+            let scanned = scan_code(
+                &code,
+                Some(path.display().to_string()),
+                Dialect::Luau,
+                &mut Vec::new(),
+            )?;
+            let parsed = parse_tokens(&scanned, Some(path.display().to_string()), &mut Vec::new())?;
+            let options = CompilerOptions {
+                preserve_types: true,
+                ..Default::default()
+            };
+            let compiled = compile_ast(parsed, None, &options, &mut Vec::new());
+            let settings = settings!("compiler-typed", path);
+
+            settings.bind(|| {
+                insta::assert_snapshot!(compiled);
+            });
+
+            Ok(())
+        }
+
+        gen_tests!("test-data/luau", scan);
+        gen_tests!("test-data/luau", parse);
+        gen_tests!("test-data/luau", compile);
+        gen_tests!("test-data/luau", round_trip);
+        gen_tests!("test-data/luau", source_map);
+        gen_tests!("test-data/luau", visit);
+        #[cfg(feature = "serde")]
+        gen_tests!("test-data/luau", serialize);
+        gen_tests!("test-data/luau", compile_typed);
+    }
+
+    mod glua {
+        use super::*;
+
+        gen_tests!("test-data/glua", scan);
+        gen_tests!("test-data/glua", parse);
+        gen_tests!("test-data/glua", compile);
+        gen_tests!("test-data/glua", round_trip);
+        gen_tests!("test-data/glua", source_map);
+        gen_tests!("test-data/glua", visit);
+        #[cfg(feature = "serde")]
+        gen_tests!("test-data/glua", serialize);
+    }
+
+    mod idioms {
+        use super::*;
+
+        fn compile_idiomatic(path: PathBuf) -> Result<(), Diagnostic> {
+            let code = std::fs::read_to_string(&path).unwrap();
+            let scanned = scan_code(
+                &code,
+                Some(path.display().to_string()),
+                Dialect::Lua,
+                &mut Vec::new(),
+            )?;
+            let parsed = parse_tokens(&scanned, Some(path.display().to_string()), &mut Vec::new())?;
+            let options = CompilerOptions {
+                compound_assignment: true,
+                ternary: true,
+                safe_navigation: true,
+                match_statements: true,
+                enums: true,
+                continue_statements: true,
+                default_parameters: true,
+                ..Default::default()
+            };
+            let compiled = compile_ast(parsed, None, &options, &mut Vec::new());
+            let settings = settings!("compiler-idiomatic", path);
+
+            settings.bind(|| {
+                insta::assert_snapshot!(compiled);
+            });
+
+            Ok(())
+        }
+
+        gen_tests!("test-data/idioms", compile);
+        gen_tests!("test-data/idioms", compile_idiomatic);
+    }
+
+    mod warnings {
         use super::*;
-        fn compile(path: PathBuf) -> Result<(), Diagnostic> {
+        use cluna::{convert, ConvertOptions};
+
+        fn diagnostics(path: PathBuf) -> Result<(), Diagnostic> {
             let code = std::fs::read_to_string(&path).unwrap();
-            let scanned = scan_code(code, Some(path.display().to_string()))?;
-            let parsed = parse_tokens(&scanned, Some(path.display().to_string()))?;
-            compile_ast(parsed);
+            let options = ConvertOptions {
+                path: Some(path.display().to_string()),
+                compiler: CompilerOptions {
+                    ternary: true,
+                    enums: true,
+                    ..Default::default()
+                },
+                ..Default::default()
+            };
+            let converted = convert(&code, &options);
+            let settings = settings!("diagnostics", path);
+
+            settings.bind(|| {
+                insta::assert_debug_snapshot!(converted.diagnostics);
+            });
 
             Ok(())
         }
 
+        gen_tests!("test-data/warnings", diagnostics);
+    }
+
+    mod negative {
+        use super::*;
+        use cluna::{convert, error::SourceDb, ConvertOptions};
+
         fn should_fail(path: PathBuf) -> Result<(), ()> {
-            compile(path).map_or(Ok(()), |_| Err(()))
+            let code = std::fs::read_to_string(&path).unwrap();
+            let converted = convert(&code, &ConvertOptions::default());
+
+            match converted.code {
+                None if !converted.diagnostics.is_empty() => Ok(()),
+                _ => Err(()),
+            }
         }
 
         gen_tests!("test-data/negative", should_fail);
+
+        #[test]
+        fn lexer_continues_after_unexpected_characters() {
+            let tokens = cluna::lexer::Lexer::new("a @ b", None, Dialect::Lua).collect::<Vec<_>>();
+
+            assert!(matches!(tokens[..], [Ok(_), Err(_), Ok(_)]));
+        }
+
+        #[test]
+        fn render_quotes_known_sources() {
+            let code = "local x = 1\nprint(x +)\n";
+            let options = ConvertOptions {
+                path: Some("bad.lua".to_owned()),
+                ..Default::default()
+            };
+            let error = convert(code, &options).diagnostics.pop().unwrap();
+            let mut sources = SourceDb::new();
+
+            assert!(!error.render(&sources).contains("print(x +)"));
+            sources.insert("bad.lua".to_owned(), code.to_owned());
+            assert!(error.render(&sources).contains("print(x +)"));
+            assert_eq!(error.to_string(), error.render(&SourceDb::new()));
+            assert!(!error.render_plain(&sources).contains('\x1b'));
+        }
+
+        #[test]
+        fn convert_reports_lexer_errors() {
+            let converted = convert("local x = @", &ConvertOptions::default());
+
+            assert!(converted.code.is_none());
+            assert_eq!(converted.diagnostics[0].message(), "unexpected @");
+        }
+    }
+
+    #[cfg(feature = "ffi")]
+    mod ffi {
+        use std::ffi::CStr;
+
+        use cluna::ffi::{
+            cluna_convert, cluna_options_default, cluna_result_free, ClunaDialect, ClunaLevel,
+        };
+
+        #[test]
+        fn converts_through_the_c_api() {
+            let source = "local a <const> = 1\nlocal b = a";
+            let mut options = cluna_options_default();
+            options.dialect = ClunaDialect::Luau;
+            options.path = c"const.lua".as_ptr();
+
+            unsafe {
+                let result = cluna_convert(source.as_ptr().cast(), source.len(), &options);
+                let code = CStr::from_ptr((*result).code).to_str().unwrap();
+                assert_eq!(code.len(), (*result).code_len);
+                assert!(code.contains("local b = a"));
+
+                assert_eq!((*result).diagnostics_len, 1);
+                let warning = &*(*result).diagnostics;
+                assert!(matches!(warning.level, ClunaLevel::Warning));
+                assert_eq!(CStr::from_ptr(warning.path).to_str(), Ok("const.lua"));
+                assert_eq!(warning.line, 1);
+                cluna_result_free(result);
+            }
+        }
+
+        #[test]
+        fn reports_failures_through_the_c_api() {
+            unsafe {
+                let result = cluna_convert(c"local = 1".as_ptr(), 9, std::ptr::null());
+                assert!((*result).code.is_null());
+                assert!((*result).diagnostics_len > 0);
+                cluna_result_free(result);
+
+                let result = cluna_convert(std::ptr::null(), 0, std::ptr::null());
+                assert!(!(*result).code.is_null());
+                cluna_result_free(result);
+            }
+        }
+
+        #[test]
+        fn committed_header_is_up_to_date() {
+            // the build writes the header it generates to OUT_DIR
+            let generated = include_str!(concat!(env!("OUT_DIR"), "/cluna.h"));
+            let committed = include_str!("../include/cluna.h");
+            assert!(
+                generated == committed,
+                "include/cluna.h is out of date, copy it from {}",
+                concat!(env!("OUT_DIR"), "/cluna.h")
+            );
+        }
     }
 }
@@ -1,85 +1,84 @@
-use crate::{error::Diagnostic, lexer::Lexer};
+use crate::{
+    error::{Diagnostic, DiagnosticLevel},
+    lexer::Lexer,
+};
 use std::fmt;
 
 #[derive(Debug, PartialEq, Clone)]
-pub struct HexDecimal {
-    before_decimal: String,
-    after_decimal: String,
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Number {
+    Integer {
+        value: i64,
+        literal: String,
+    },
+    Float {
+        #[cfg_attr(feature = "serde", serde(with = "float"))]
+        value: f64,
+        literal: String,
+    },
 }
 
-impl HexDecimal {
-    #[inline]
-    fn new(before_decimal: String, after_decimal: String) -> Self {
-        HexDecimal {
-            before_decimal,
-            after_decimal,
-        }
-    }
+// JSON has no infinities, the value of a literal like `1e400` is stored as a string instead
+#[cfg(feature = "serde")]
+mod float {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-    fn to_decimal(&self) -> f64 {
-        let mut num = 0.0f64;
+    #[derive(Serialize, Deserialize)]
+    #[serde(untagged)]
+    enum Float {
+        Finite(f64),
+        Other(String),
+    }
 
-        for (i, c) in (0i32..).zip(self.before_decimal.chars().rev()) {
-            let digit = c.to_digit(16).unwrap() as f64;
-            num += digit * 16f64.powi(i);
+    pub fn serialize<S: Serializer>(value: &f64, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_finite() {
+            Float::Finite(*value)
+        } else {
+            Float::Other(value.to_string())
         }
+        .serialize(serializer)
+    }
 
-        for (i, c) in (0i32..).zip(self.after_decimal.chars().skip(1)) {
-            let digit = c.to_digit(16).unwrap() as f64;
-            num += digit * 16f64.powi(-(i + 1));
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+        match Float::deserialize(deserializer)? {
+            Float::Finite(value) => Ok(value),
+            Float::Other(value) => value.parse().map_err(serde::de::Error::custom),
         }
-
-        num
     }
 }
 
-#[derive(Debug, PartialEq, Clone)]
-pub struct Decimal {
-    before_decimal: String,
-    after_decimal: String,
+fn hex_digits(digits: &str) -> impl Iterator<Item = u64> + '_ {
+    digits.chars().filter_map(|c| c.to_digit(16)).map(u64::from)
 }
 
-#[inline]
-fn normalize_before_decimal(before_decimal: String) -> String {
-    if before_decimal.is_empty() {
-        "0".to_string()
-    } else {
-        before_decimal
+fn scale(mut value: f64, mut exponent: i64) -> f64 {
+    while exponent != 0 && value != 0.0 && value.is_finite() {
+        let step = exponent.clamp(-1000, 1000);
+        value *= 2f64.powi(step as i32);
+        exponent -= step;
     }
+    value
 }
 
-#[inline]
-fn normalize_after_decimal(after_decimal: String) -> String {
-    if after_decimal == "." {
-        ".0".to_string()
-    } else {
-        after_decimal
-    }
-}
+fn normalize_decimal(lexeme: &str) -> String {
+    let (mantissa, exponent) = match lexeme.find(['e', 'E']) {
+        Some(i) => (&lexeme[..i], Some(&lexeme[i + 1..])),
+        None => (lexeme, None),
+    };
 
-impl Decimal {
-    #[inline]
-    fn new(before_decimal: String, after_decimal: String) -> Self {
-        Decimal {
-            before_decimal: normalize_before_decimal(before_decimal),
-            after_decimal: normalize_after_decimal(after_decimal),
-        }
+    let mut literal = String::with_capacity(lexeme.len() + 2);
+    if mantissa.starts_with('.') {
+        literal.push('0');
     }
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum Number {
-    Hex(String),
-    HexDecimal(HexDecimal),
-    HexScientific {
-        mantissa: HexDecimal,
-        exponent: String,
-    },
-    Decimal(Decimal),
-    Scientific {
-        mantissa: Decimal,
-        exponent: String,
-    },
+    literal += mantissa;
+    if mantissa.ends_with('.') {
+        literal.push('0');
+    }
+    if let Some(exponent) = exponent {
+        literal.push('e');
+        literal += exponent;
+    }
+    literal
 }
 
 impl Number {
@@ -91,21 +90,10 @@ impl Number {
         let mut is_hex = false;
         let mut sign_encountered = false;
 
-        let mut before_decimal = String::with_capacity(4);
-        let mut after_decimal = String::with_capacity(4);
-        let mut exponent = String::new();
-
         while let Some(c) = lexer.advance() {
             match c {
                 '0'..='9' => {
                     digit_encountered = true;
-                    if is_scientific {
-                        exponent.push(c);
-                    } else if decimal_encountered {
-                        after_decimal.push(c);
-                    } else {
-                        before_decimal.push(c);
-                    }
                 }
                 '.' => {
                     if decimal_encountered {
@@ -127,7 +115,7 @@ impl Number {
 
                     if is_hex
                         && !digit_encountered
-                        && !lexer.peek().map_or(false, |c| c.is_ascii_hexdigit())
+                        && !lexer.peek().is_some_and(|c| c.is_ascii_hexdigit())
                     {
                         return Err(Diagnostic::new(
                             "malformed number".to_owned(),
@@ -138,7 +126,7 @@ impl Number {
 
                     if !is_hex
                         && !digit_encountered
-                        && !lexer.peek().map_or(false, |c| c.is_ascii_digit())
+                        && !lexer.peek().is_some_and(|c| c.is_ascii_digit())
                     {
                         return Err(Diagnostic::new(
                             "malformed number".to_owned(),
@@ -146,15 +134,14 @@ impl Number {
                             lexer.position.with_span(start..lexer.current),
                         ));
                     }
-                    after_decimal.push(c);
                 }
                 'x' | 'X' => {
-                    if !lexer.look_back().map_or(false, |c| c == '0')
+                    if lexer.look_back() != Some('0')
                         || is_scientific
                         || is_hex
                         || !lexer
                             .peek()
-                            .map_or(false, |c| c.is_ascii_hexdigit() || c == '.')
+                            .is_some_and(|c| c.is_ascii_hexdigit() || c == '.')
                     {
                         return Err(Diagnostic::new(
                             "malformed number".to_owned(),
@@ -167,13 +154,14 @@ impl Number {
                 }
                 'e' | 'E' => {
                     if !is_scientific && is_hex {
+                        digit_encountered = true;
                         continue;
                     }
 
                     if !digit_encountered
                         || !lexer
                             .peek()
-                            .map_or(false, |c| c.is_ascii_digit() || c == '+' || c == '-')
+                            .is_some_and(|c| c.is_ascii_digit() || c == '+' || c == '-')
                         || is_scientific
                     {
                         return Err(Diagnostic::new(
@@ -182,7 +170,7 @@ impl Number {
                             lexer.position.with_span(start..lexer.current),
                         ));
                     } else {
-                        exponent.push(lexer.advance().unwrap());
+                        lexer.advance();
                     }
                     is_scientific = true;
                 }
@@ -197,9 +185,9 @@ impl Number {
 
                     if lexer
                         .peek()
-                        .map_or(false, |c| c == '+' || c == '-' || c.is_ascii_digit())
+                        .is_some_and(|c| c == '+' || c == '-' || c.is_ascii_digit())
                     {
-                        exponent.push(lexer.advance().unwrap());
+                        lexer.advance();
                     } else {
                         return Err(Diagnostic::new(
                             "malformed number".to_owned(),
@@ -217,11 +205,6 @@ impl Number {
                             lexer.position.with_span(start..lexer.current),
                         ));
                     }
-                    if decimal_encountered {
-                        after_decimal.push(c);
-                    } else {
-                        before_decimal.push(c);
-                    }
                     digit_encountered = true;
                 }
                 '+' | '-' => {
@@ -238,7 +221,6 @@ impl Number {
                         ));
                     }
                     sign_encountered = true;
-                    exponent.push(c);
                 }
                 _ => {
                     lexer.go_back();
@@ -248,8 +230,8 @@ impl Number {
         }
 
         if is_scientific
-            && !(lexer.source[lexer.current - 1].is_ascii_digit()
-                || lexer.peek().map_or(false, |c| c.is_ascii_digit()))
+            && !(lexer.source.as_bytes()[lexer.current - 1].is_ascii_digit()
+                || lexer.peek().is_some_and(|c| c.is_ascii_digit()))
         {
             return Err(Diagnostic::new(
                 "malformed number".to_owned(),
@@ -266,75 +248,124 @@ impl Number {
             ));
         }
 
-        let len = lexer.current - start;
-        let lexeme: String = lexer.source[lexer.current - len..lexer.current]
-            .iter()
-            .collect();
+        let lexeme = &lexer.source[start..lexer.current];
+        let is_float = decimal_encountered || is_scientific;
+        let (number, warning) = if is_hex {
+            Number::from_hex(lexeme, is_float)
+        } else {
+            Number::from_decimal(lexeme, is_float)
+        };
 
-        let number = if is_scientific {
-            if is_hex {
-                let mantissa = HexDecimal::new(before_decimal, after_decimal);
-                Number::HexScientific { mantissa, exponent }
-            } else {
-                let mantissa = Decimal::new(before_decimal, after_decimal);
-                Number::Scientific { mantissa, exponent }
+        if let Some(warning) = warning {
+            lexer.diagnostics.push(
+                Diagnostic::new(
+                    warning,
+                    lexer.path.clone(),
+                    lexer.position.with_span(start..lexer.current),
+                )
+                .level(DiagnosticLevel::Warning),
+            );
+        }
+
+        Ok(number)
+    }
+
+    fn from_decimal(lexeme: &str, is_float: bool) -> (Number, Option<String>) {
+        let literal = normalize_decimal(lexeme);
+
+        if !is_float {
+            if let Ok(value) = literal.parse::<i64>() {
+                return (Number::Integer { value, literal }, None);
+            }
+        }
+
+        let value = literal.parse::<f64>().unwrap();
+        let warning = if value.is_infinite() {
+            Some("number literal overflows to infinity".to_owned())
+        } else if !is_float {
+            Some("integer literal does not fit in 64 bits, it will be a float".to_owned())
+        } else {
+            None
+        };
+
+        (Number::Float { value, literal }, warning)
+    }
+
+    fn from_hex(lexeme: &str, is_float: bool) -> (Number, Option<String>) {
+        let digits = &lexeme[2..];
+
+        if !is_float {
+            let mut value = 0u64;
+            let mut wrapped = false;
+            for digit in hex_digits(digits) {
+                wrapped |= value >> 60 != 0;
+                value = value.wrapping_mul(16).wrapping_add(digit);
             }
-        } else if is_hex {
-            if decimal_encountered {
-                let hex_decimal = HexDecimal::new(before_decimal[1..].to_string(), after_decimal);
-                Number::HexDecimal(hex_decimal)
+
+            let value = value as i64;
+            let warning = wrapped.then(|| {
+                format!("hexadecimal literal does not fit in 64 bits, it wraps around to {value}")
+            });
+            let literal = lexeme.to_owned();
+            return (Number::Integer { value, literal }, warning);
+        }
+
+        let (mantissa, exponent) = match digits.find(['p', 'P']) {
+            Some(i) => (&digits[..i], &digits[i + 1..]),
+            None => (digits, "0"),
+        };
+        let (whole, fraction) = mantissa.split_once('.').unwrap_or((mantissa, ""));
+        let mut exponent = exponent
+            .parse::<i64>()
+            .unwrap_or(if exponent.starts_with('-') {
+                i64::MIN / 2
+            } else {
+                i64::MAX / 2
+            });
+
+        let mut significand = 0u64;
+        for digit in hex_digits(whole) {
+            if significand >> 60 != 0 {
+                exponent = exponent.saturating_add(4);
             } else {
-                Number::Hex(lexeme)
+                significand = significand * 16 + digit;
             }
-        } else if decimal_encountered {
-            let decimal = Decimal::new(before_decimal, after_decimal);
-            Number::Decimal(decimal)
+        }
+        for digit in hex_digits(fraction) {
+            if significand >> 60 == 0 {
+                significand = significand * 16 + digit;
+                exponent = exponent.saturating_sub(4);
+            }
+        }
+
+        let value = scale(significand as f64, exponent);
+        if value.is_infinite() {
+            let warning = Some("number literal overflows to infinity".to_owned());
+            let literal = "1e999".to_owned();
+            (Number::Float { value, literal }, warning)
         } else {
-            Number::Decimal(Decimal::new(before_decimal, after_decimal))
-        };
-        Ok(number)
+            let literal = format!("{value:?}");
+            (Number::Float { value, literal }, None)
+        }
+    }
+
+    pub const fn is_integer(&self) -> bool {
+        matches!(self, Number::Integer { .. })
+    }
+
+    pub fn as_f64(&self) -> f64 {
+        match self {
+            Number::Integer { value, .. } => *value as f64,
+            Number::Float { value, .. } => *value,
+        }
     }
 }
 
 impl fmt::Display for Number {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match &self {
-            Number::Hex(hex) => write!(f, "{}", hex),
-            Number::HexDecimal(hex) => {
-                let number = hex.to_decimal().to_string();
-                if number == "inf" {
-                    write!(f, "1 / 0")
-                } else if number == "-inf" {
-                    write!(f, "-1 / 0")
-                } else {
-                    write!(f, "{}", number)
-                }
-            }
-            Number::HexScientific { mantissa, exponent } => {
-                let number = mantissa.to_decimal();
-                let number = number * 2f64.powi(exponent.parse::<i32>().unwrap());
-                let number = number.to_string();
-
-                if number == "inf" {
-                    write!(f, "1 / 0")
-                } else if number == "-inf" {
-                    write!(f, "-1 / 0")
-                } else {
-                    write!(f, "{}", number)
-                }
-            }
-            Number::Decimal(Decimal {
-                before_decimal,
-                after_decimal,
-            }) => {
-                write!(f, "{}{}", before_decimal, after_decimal)
-            }
-            Number::Scientific { mantissa, exponent } => {
-                write!(
-                    f,
-                    "{}{}e{}",
-                    mantissa.before_decimal, mantissa.after_decimal, exponent
-                )
+        match self {
+            Number::Integer { literal, .. } | Number::Float { literal, .. } => {
+                write!(f, "{}", literal)
             }
         }
     }
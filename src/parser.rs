@@ -1,9 +1,16 @@
 use crate::{
     error::{Diagnostic, DiagnosticLevel},
-    lexer::{Position, Token, TokenType},
+    lexer::{strip_comments, Position, Token, TokenType},
     number::Number,
 };
-use std::{collections::VecDeque, rc::Rc};
+use std::{collections::VecDeque, sync::Arc};
+
+mod visit;
+
+pub use visit::{
+    fold_block, fold_expression, fold_token, walk_block, walk_block_mut, walk_expression,
+    walk_expression_mut, walk_token, walk_token_mut, Fold, Visitor, VisitorMut,
+};
 
 macro_rules! vec_deque {
     ($($elem:expr),*) => {
@@ -12,35 +19,70 @@ macro_rules! vec_deque {
 }
 macro_rules! complex_token {
     ($t:expr, $($expr:tt)+) => {
-        $crate::parser::ComplexToken::new($crate::parser::ComplexTokenKind::$($expr)+, $t.leading(), $t.trailing())
+        $crate::parser::ComplexToken::new($crate::parser::ComplexTokenKind::$($expr)+, $t.leading(), $t.trailing(), $t.position())
     };
 }
 
 pub type Expression = VecDeque<ComplexToken>;
-pub type FunctionArgs = Vec<Rc<str>>;
+pub type FunctionArgs = Vec<(TypedName, Option<Expression>)>;
 type OptionalEnd = Option<(TokenType, &'static str)>;
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct TypedName {
+    pub name: Arc<str>,
+    pub ty: Option<Arc<str>>,
+}
+
+impl TypedName {
+    fn new(name: Arc<str>) -> Self {
+        Self { name, ty: None }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub enum ComplexTokenKind {
     Variable {
-        names: Vec<(Rc<str>, bool)>,
+        names: Vec<(TypedName, bool)>,
         values: Vec<Expression>,
     },
     Alter {
         names: Vec<ComplexToken>,
         values: Vec<Expression>,
     },
+    CompoundAlter {
+        name: Box<ComplexToken>,
+        operator: Arc<str>,
+        value: Expression,
+    },
     Table(Vec<(Option<Expression>, Expression)>),
     Function {
         local: bool,
         name: Expression,
+        generics: Option<Arc<str>>,
+        args: FunctionArgs,
+        returns: Option<Arc<str>>,
+        body: CodeBlock,
+    },
+    Method {
+        name: Expression,
+        generics: Option<Arc<str>>,
         args: FunctionArgs,
+        returns: Option<Arc<str>>,
         body: CodeBlock,
     },
     Lambda {
+        generics: Option<Arc<str>>,
         args: FunctionArgs,
+        returns: Option<Arc<str>>,
         body: CodeBlock,
     },
+    Ternary {
+        condition: Expression,
+        then: Expression,
+        otherwise: Expression,
+    },
     IfStatement {
         condition: Expression,
         body: CodeBlock,
@@ -55,44 +97,66 @@ pub enum ComplexTokenKind {
         body: CodeBlock,
     },
     ForLoop {
-        iter: Rc<str>,
+        iter: TypedName,
         start: Expression,
         end: Expression,
         step: Option<Expression>,
         code: CodeBlock,
     },
     ForFuncLoop {
-        iters: Vec<Rc<str>>,
+        iters: Vec<TypedName>,
         expr: Expression,
         stop: Option<Expression>,
         initial: Option<Expression>,
+        closing: Option<Expression>,
         code: CodeBlock,
     },
     Ident(Expression),
-    MultilineString(Rc<str>),
+    MultilineString(Arc<str>),
+    InterpolatedString {
+        parts: Vec<Arc<str>>,
+        values: Vec<Expression>,
+    },
     Number(Number),
-    Symbol(Rc<str>),
-    Operator((Rc<str>, bool)),
+    Symbol(Arc<str>),
+    Operator((Arc<str>, bool)),
     Call(Vec<Expression>),
     Expr(Expression),
     DoBlock(CodeBlock),
     Return(Option<Vec<Expression>>),
     Break,
+    Continue,
+    TypeCast(Arc<str>),
+    TypeAlias {
+        export: bool,
+        name: Arc<str>,
+        generics: Option<Arc<str>>,
+        value: Arc<str>,
+    },
+    Enum(Expression),
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct ComplexToken {
+    // the trivia before the first token of a statement, its `leading` and `trailing` come from
+    // the last one
+    prefix: String,
     leading: String,
     kind: ComplexTokenKind,
     trailing: String,
+    // where the token starts, statements start at their first token
+    position: Position,
 }
 
 impl ComplexToken {
-    fn new(kind: ComplexTokenKind, leading: String, trailing: String) -> Self {
+    pub fn new(kind: ComplexTokenKind, leading: &str, trailing: &str, position: Position) -> Self {
         Self {
+            prefix: String::new(),
             kind,
-            leading,
-            trailing,
+            leading: leading.to_owned(),
+            trailing: trailing.to_owned(),
+            position,
         }
     }
 
@@ -100,39 +164,177 @@ impl ComplexToken {
         &self.kind
     }
 
+    pub fn token_mut(&mut self) -> &mut ComplexTokenKind {
+        &mut self.kind
+    }
+
+    pub fn prefix(&self) -> &str {
+        &self.prefix
+    }
+
     pub fn leading(&self) -> &str {
         &self.leading
     }
 
+    pub(crate) fn set_leading(&mut self, leading: &str) {
+        self.leading = leading.to_owned();
+    }
+
     pub fn trailing(&self) -> &str {
         &self.trailing
     }
+
+    pub fn position(&self) -> Position {
+        self.position.clone()
+    }
 }
 
 #[derive(Clone, Debug, PartialEq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct CodeBlock {
+    // the trivia after the keyword opening the block
+    pub leading: String,
     pub start: usize,
     pub code: Expression,
     pub end: usize,
 }
 
+// tokens after which `if` starts an if expression instead of an if statement
+fn starts_expression(kind: TokenType) -> bool {
+    use TokenType::*;
+    matches!(
+        kind,
+        Equals
+            | PlusEquals
+            | MinusEquals
+            | StarEquals
+            | SlashEquals
+            | FloorDivEquals
+            | PercentEquals
+            | CaretEquals
+            | DoubleDotEquals
+            | LeftParen
+            | LeftBracket
+            | LeftBrace
+            | Comma
+            | Return
+            | If
+            | ElseIf
+            | While
+            | Until
+            | In
+            | Plus
+            | Minus
+            | Star
+            | Slash
+            | FloorDiv
+            | Percent
+            | Caret
+            | Hash
+            | Tilde
+            | DoubleEquals
+            | NotEquals
+            | LessThan
+            | LessThanOrEqual
+            | GreaterThan
+            | GreaterThanOrEqual
+            | DoubleDot
+            | BitAnd
+            | BitOr
+            | BitShiftLeft
+            | BitShiftRight
+            | And
+            | Or
+            | Not
+            | InterpolatedStringBegin
+            | InterpolatedStringMiddle
+    )
+}
+
+struct BlockScanner {
+    scope: usize,
+    in_special_do: bool,
+    // scopes of the if expressions whose 'else' has not been reached yet
+    if_expressions: Vec<usize>,
+    in_if_expression: bool,
+    previous: Option<TokenType>,
+}
+
+impl BlockScanner {
+    fn new(previous: Option<TokenType>) -> Self {
+        Self {
+            scope: 0,
+            in_special_do: false,
+            if_expressions: Vec::new(),
+            in_if_expression: false,
+            previous,
+        }
+    }
+
+    fn scan(&mut self, t: &Token) -> bool {
+        use TokenType::*;
+
+        let previous = self.previous.replace(t.kind());
+        let in_if_expression = std::mem::take(&mut self.in_if_expression);
+        let if_expression = self.if_expressions.last() == Some(&self.scope);
+
+        match t.kind() {
+            If if in_if_expression || previous.is_some_and(starts_expression) => {
+                self.if_expressions.push(self.scope);
+                true
+            }
+            Then | ElseIf if if_expression => {
+                self.in_if_expression = true;
+                true
+            }
+            Else if if_expression => {
+                self.if_expressions.pop();
+                self.in_if_expression = true;
+                true
+            }
+            Function | Repeat | If => {
+                self.scope += 1;
+                true
+            }
+            Do => {
+                if !self.in_special_do {
+                    self.scope += 1;
+                }
+                self.in_special_do = false;
+                true
+            }
+            While | For => {
+                self.scope += 1;
+                self.in_special_do = true;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
 struct Parser<'a> {
-    tokens: &'a [Token],
+    tokens: &'a [Token<'a>],
     path: Option<String>,
     expr: Expression,
     current: usize,
     position: Position,
+    // set when a '>>' token closed two generic lists at once
+    pending_greater: bool,
+    diagnostics: Vec<Diagnostic>,
 }
 
 impl<'a> Parser<'a> {
     #[inline]
-    pub fn new(tokens: &'a [Token], path: Option<String>) -> Self {
+    pub fn new(tokens: &'a [Token<'a>], path: Option<String>) -> Self {
         Self {
             tokens,
             path,
             position: Position::new(0, 0, 0..0),
             current: 0,
             expr: Expression::with_capacity(16),
+            pending_greater: false,
+            diagnostics: Vec::new(),
         }
     }
 
@@ -141,7 +343,7 @@ impl<'a> Parser<'a> {
         self.current >= self.tokens.len()
     }
 
-    fn advance(&mut self) -> Option<&Token> {
+    fn advance(&mut self) -> Option<&Token<'a>> {
         if self.done() {
             None
         } else {
@@ -217,7 +419,11 @@ impl<'a> Parser<'a> {
         Ok(())
     }
 
-    fn assert_advance(&mut self, expected: TokenType, error: &str) -> Result<&Token, Diagnostic> {
+    fn assert_advance(
+        &mut self,
+        expected: TokenType,
+        error: &str,
+    ) -> Result<&Token<'a>, Diagnostic> {
         if !self.advance_if(expected) {
             let t = self.peek().ok_or_else(|| {
                 Diagnostic::unexpected(
@@ -236,7 +442,7 @@ impl<'a> Parser<'a> {
         Ok(self.current())
     }
 
-    fn go_back(&mut self) -> Option<&Token> {
+    fn go_back(&mut self) -> Option<&Token<'a>> {
         if self.current == 0 {
             None
         } else {
@@ -247,7 +453,7 @@ impl<'a> Parser<'a> {
         }
     }
 
-    fn peek(&self) -> Option<Token> {
+    fn peek(&self) -> Option<Token<'a>> {
         if self.done() {
             None
         } else {
@@ -255,8 +461,19 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn peek_at(&self, offset: usize) -> Option<Token<'a>> {
+        self.tokens.get(self.current + offset - 1).cloned()
+    }
+
+    fn previous_kind(&self) -> Option<TokenType> {
+        self.current
+            .checked_sub(1)
+            .and_then(|i| self.tokens.get(i))
+            .map(|t| t.kind())
+    }
+
     #[inline]
-    fn current(&self) -> &Token {
+    fn current(&self) -> &Token<'a> {
         &self.tokens[self.current.saturating_sub(1)]
     }
 
@@ -271,7 +488,7 @@ impl<'a> Parser<'a> {
 
             if t.kind() != TokenType::Comma {
                 if let Some((end_token, expected_end)) = end {
-                    if self.peek().map_or(false, |t| t.kind() != end_token) {
+                    if self.peek().is_some_and(|t| t.kind() != end_token) {
                         self.advance();
 
                         return Err(Diagnostic::expected_found(
@@ -420,6 +637,21 @@ impl<'a> Parser<'a> {
             return Ok(ident);
         }
 
+        if self.check_compound() {
+            let t = self.advance().unwrap().clone();
+            let value = self.parse_expression(None)?;
+            self.advance_if(TokenType::Semicolon);
+
+            return Ok(complex_token!(
+                t,
+                CompoundAlter {
+                    name: Box::new(ident),
+                    operator: t.lexeme().as_symbol(),
+                    value,
+                }
+            ));
+        }
+
         if let Some(
             t @ Token {
                 kind: TokenType::Equals | TokenType::Comma,
@@ -477,10 +709,49 @@ impl<'a> Parser<'a> {
                     | TripleDot
                     | Hash
                     | Not
+                    | InterpolatedString
+                    | InterpolatedStringBegin
+            )
+        )
+    }
+
+    fn check_compound(&self) -> bool {
+        use TokenType::*;
+        matches!(
+            self.peek().map(|t| t.kind()),
+            Some(
+                PlusEquals
+                    | MinusEquals
+                    | StarEquals
+                    | SlashEquals
+                    | FloorDivEquals
+                    | PercentEquals
+                    | CaretEquals
+                    | DoubleDotEquals
             )
         )
     }
 
+    // `continue` is only a keyword when it can't be the start of a call or assignment
+    fn check_continue(&self) -> bool {
+        use TokenType::*;
+        !self.check_compound()
+            && !matches!(
+                self.peek().map(|t| t.kind()),
+                Some(
+                    LeftParen
+                        | Dot
+                        | Colon
+                        | LeftBracket
+                        | Equals
+                        | Comma
+                        | String
+                        | MultilineString
+                        | LeftBrace
+                )
+            )
+    }
+
     fn check_op(&mut self) -> bool {
         use TokenType::*;
         matches!(
@@ -501,7 +772,10 @@ impl<'a> Parser<'a> {
                     | Not
                     | Tilde
                     | Minus
-                    | Function,
+                    | Function
+                    | If
+                    | InterpolatedString
+                    | InterpolatedStringBegin,
             )
         )
     }
@@ -553,61 +827,46 @@ impl<'a> Parser<'a> {
         Ok(complex_token!(last, Table(data)))
     }
 
-    fn parse_code_block_common(scope: &mut usize, in_special_do: &mut bool, t: &Token) -> bool {
-        use TokenType::*;
-
-        match t.kind() {
-            Function | Repeat | If => {
-                *scope += 1;
-                true
-            }
-            Do => {
-                if !*in_special_do {
-                    *scope += 1;
-                }
-                *in_special_do = false;
-                true
-            }
-            While | For => {
-                *scope += 1;
-                *in_special_do = true;
-                true
-            }
-            _ => false,
-        }
+    // the block runs from `start` to the keyword that was just reached
+    fn code_block(&mut self, start: usize) -> Result<CodeBlock, Diagnostic> {
+        Ok(CodeBlock {
+            leading: start.checked_sub(1).map_or_else(String::new, |opener| {
+                self.tokens[opener].trailing().to_owned()
+            }),
+            code: parse_tokens(
+                &self.tokens[start..self.current.saturating_sub(1)],
+                self.path.clone(),
+                &mut self.diagnostics,
+            )?,
+            start,
+            end: self.current,
+        })
     }
+
     fn parse_code_block(&mut self) -> Result<CodeBlock, Diagnostic> {
         let start = self.current;
-        let mut scope = 0;
-        let mut in_special_do = false;
+        let mut scanner = BlockScanner::new(self.previous_kind());
 
         while let Some(t) = self.advance() {
             use TokenType::*;
-            if !Self::parse_code_block_common(&mut scope, &mut in_special_do, t) {
+            if !scanner.scan(t) {
                 match t.kind() {
                     End => {
-                        if scope == 0 {
-                            return Ok(CodeBlock {
-                                code: parse_tokens(
-                                    &self.tokens[start..self.current.saturating_sub(1)],
-                                    self.path.clone(),
-                                )?,
-                                start,
-                                end: self.current,
-                            });
+                        if scanner.scope == 0 {
+                            return self.code_block(start);
                         } else {
-                            scope -= 1;
+                            scanner.scope -= 1;
                         }
                     }
                     Until => {
-                        if scope == 0 {
+                        if scanner.scope == 0 {
                             return Err(Diagnostic::unexpected(
                                 "'until'".to_owned(),
                                 self.path.clone(),
                                 self.position.clone(),
                             ));
                         } else {
-                            scope -= 1;
+                            scanner.scope -= 1;
                         }
                     }
                     _ => {}
@@ -623,36 +882,28 @@ impl<'a> Parser<'a> {
 
     fn parse_repeat_block(&mut self) -> Result<CodeBlock, Diagnostic> {
         let start = self.current;
-        let mut scope = 0;
-        let mut in_special_do = false;
+        let mut scanner = BlockScanner::new(self.previous_kind());
 
         while let Some(t) = self.advance() {
             use TokenType::*;
-            if !Self::parse_code_block_common(&mut scope, &mut in_special_do, t) {
+            if !scanner.scan(t) {
                 match t.kind() {
                     End => {
-                        if scope == 0 {
+                        if scanner.scope == 0 {
                             return Err(Diagnostic::unexpected(
                                 "'end'".to_owned(),
                                 self.path.clone(),
                                 self.position.clone(),
                             ));
                         } else {
-                            scope -= 1;
+                            scanner.scope -= 1;
                         }
                     }
                     Until => {
-                        if scope == 0 {
-                            return Ok(CodeBlock {
-                                code: parse_tokens(
-                                    &self.tokens[start..self.current.saturating_sub(1)],
-                                    self.path.clone(),
-                                )?,
-                                start,
-                                end: self.current,
-                            });
+                        if scanner.scope == 0 {
+                            return self.code_block(start);
                         } else {
-                            scope -= 1;
+                            scanner.scope -= 1;
                         }
                     }
                     _ => {}
@@ -669,49 +920,32 @@ impl<'a> Parser<'a> {
 
     fn parse_if_block(&mut self) -> Result<CodeBlock, Diagnostic> {
         let start = self.current;
-        let mut scope = 0;
-        let mut in_special_do = false;
+        let mut scanner = BlockScanner::new(self.previous_kind());
 
         while let Some(t) = self.advance() {
             use TokenType::*;
 
-            if !Self::parse_code_block_common(&mut scope, &mut in_special_do, t) {
+            if !scanner.scan(t) {
                 match t.kind() {
                     End => {
-                        if scope == 0 {
-                            return Ok(CodeBlock {
-                                code: parse_tokens(
-                                    &self.tokens[start..self.current.saturating_sub(1)],
-                                    self.path.clone(),
-                                )?,
-                                start,
-                                end: self.current,
-                            });
+                        if scanner.scope == 0 {
+                            return self.code_block(start);
                         } else {
-                            scope -= 1;
+                            scanner.scope -= 1;
                         }
                     }
-                    ElseIf | Else => {
-                        if scope == 0 {
-                            return Ok(CodeBlock {
-                                code: parse_tokens(
-                                    &self.tokens[start..self.current.saturating_sub(1)],
-                                    self.path.clone(),
-                                )?,
-                                start,
-                                end: self.current,
-                            });
-                        }
+                    ElseIf | Else if scanner.scope == 0 => {
+                        return self.code_block(start);
                     }
                     Until => {
-                        if scope == 0 {
+                        if scanner.scope == 0 {
                             return Err(Diagnostic::unexpected(
                                 "'until'".to_owned(),
                                 self.path.clone(),
                                 self.position.clone(),
                             ));
                         } else {
-                            scope -= 1;
+                            scanner.scope -= 1;
                         }
                     }
                     _ => {}
@@ -759,6 +993,30 @@ impl<'a> Parser<'a> {
                             break t;
                         }
                     }
+                    InterpolatedString | InterpolatedStringBegin => {
+                        let string = self.parse_interpolated_string(&t)?;
+                        expr.push_back(string);
+                        if self.check_val() {
+                            break t;
+                        }
+                    }
+                    If if expr.back().is_none_or(|back| {
+                        matches!(back.token(), ComplexTokenKind::Operator(_))
+                    }) =>
+                    {
+                        let ternary = self.parse_if_expression(&t)?;
+                        expr.push_back(ternary);
+                        if self.check_val() {
+                            break t;
+                        }
+                    }
+                    DoubleColon if !expr.is_empty() => {
+                        let ty = self.parse_type()?;
+                        expr.push_back(complex_token!(t, TypeCast(ty)));
+                        if self.check_val() {
+                            break t;
+                        }
+                    }
                     LeftBrace => {
                         let table = self.parse_table()?;
                         expr.push_back(table);
@@ -806,7 +1064,7 @@ impl<'a> Parser<'a> {
                     }
                     Minus => {
                         if expr.is_empty()
-                            || expr.back().map_or(false, |back| {
+                            || expr.back().is_some_and(|back| {
                                 matches!(back.token(), ComplexTokenKind::Operator((_, true)))
                             })
                         {
@@ -833,7 +1091,7 @@ impl<'a> Parser<'a> {
                     }
                     Tilde => {
                         if expr.is_empty()
-                            || expr.back().map_or(false, |back| {
+                            || expr.back().is_some_and(|back| {
                                 matches!(back.kind, ComplexTokenKind::Operator((_, true)))
                             })
                         {
@@ -859,15 +1117,25 @@ impl<'a> Parser<'a> {
                         }
                     }
                     Function
-                        if self
-                            .peek()
-                            .map_or(false, |t| t.kind() == TokenType::LeftParen) =>
+                        if self.peek().is_some_and(|t| {
+                            matches!(t.kind(), TokenType::LeftParen | TokenType::LessThan)
+                        }) =>
                     {
+                        let generics = self.parse_generics()?;
                         self.assert(TokenType::LeftParen, "(")?;
                         let args = self.parse_function_args()?;
+                        let returns = self.parse_type_annotation()?;
                         let body = self.parse_code_block()?;
 
-                        expr.push_back(complex_token!(t, Lambda { args, body }));
+                        expr.push_back(complex_token!(
+                            t,
+                            Lambda {
+                                generics,
+                                args,
+                                returns,
+                                body
+                            }
+                        ));
 
                         if self.check_val() {
                             break t;
@@ -921,9 +1189,10 @@ impl<'a> Parser<'a> {
                 .clone();
 
             let lexeme = name.lexeme();
+            let ty = self.parse_type_annotation()?;
             let peek = self.peek().clone();
 
-            if peek.map_or(false, |t| t.kind() == TokenType::LessThan) {
+            if peek.is_some_and(|t| t.kind() == TokenType::LessThan) {
                 let start = self.advance().unwrap().clone();
                 let kind = self
                     .assert_advance(TokenType::Identifier, "<specifier>")?
@@ -938,26 +1207,36 @@ impl<'a> Parser<'a> {
                 );
 
                 if kind.as_symbol().as_ref() == "const" {
-                    names.push((lexeme.as_symbol(), false));
-                    eprintln!(
-                        "{}",
+                    names.push((
+                        TypedName {
+                            name: lexeme.as_symbol(),
+                            ty,
+                        },
+                        false,
+                    ));
+                    self.diagnostics.push(
                         Diagnostic::new(
                             "const variables are not supported in clue, ignoring const specifier"
                                 .to_owned(),
                             self.path.clone(),
                             position,
                         )
-                        .level(DiagnosticLevel::Warning)
+                        .level(DiagnosticLevel::Warning),
                     );
                 } else if kind.as_symbol().as_ref() == "close" {
-                    names.push((lexeme.as_symbol(), true));
-                    eprintln!(
-                        "{}",
+                    names.push((
+                        TypedName {
+                            name: lexeme.as_symbol(),
+                            ty,
+                        },
+                        true,
+                    ));
+                    self.diagnostics.push(
                         Diagnostic::new(
-                            "to-be-closed variables are not supported in clue, ignoring const specifier. Manual close calls will be added at the end of the scope, so variable shadowing will cause side effects".to_owned(),
+                            "to-be-closed variables are not supported in clue, ignoring close specifier. Manual close calls will be added at the end of the scope".to_owned(),
                             self.path.clone(),
                             position
-                        ).level(DiagnosticLevel::Warning)
+                        ).level(DiagnosticLevel::Warning),
                     );
                 } else {
                     return Err(Diagnostic::expected_found(
@@ -968,7 +1247,13 @@ impl<'a> Parser<'a> {
                     ));
                 }
             } else {
-                names.push((lexeme.as_symbol(), false));
+                names.push((
+                    TypedName {
+                        name: lexeme.as_symbol(),
+                        ty,
+                    },
+                    false,
+                ));
             }
 
             if !self.advance_if(TokenType::Comma) {
@@ -993,11 +1278,7 @@ impl<'a> Parser<'a> {
             let name = {
                 let t = self.advance().unwrap().clone();
                 match t.kind() {
-                    TokenType::Identifier => t,
-                    TokenType::TripleDot => {
-                        self.assert_compare(TokenType::RightParen, ")")?;
-                        t
-                    }
+                    TokenType::Identifier | TokenType::TripleDot => t,
                     TokenType::RightParen => {
                         break;
                     }
@@ -1011,12 +1292,20 @@ impl<'a> Parser<'a> {
                     }
                 }
             };
+            let ty = self.parse_type_annotation()?;
+            if name.kind() == TripleDot {
+                self.assert_compare(TokenType::RightParen, ")")?;
+            }
+            let arg = TypedName {
+                name: name.lexeme().as_symbol(),
+                ty,
+            };
 
             if let Some(t) = self.advance().cloned() {
                 match t.kind() {
-                    Comma => args.push(name.lexeme().as_symbol()),
+                    Comma => args.push((arg, None)),
                     RightParen => {
-                        args.push(name.lexeme().as_symbol());
+                        args.push((arg, None));
                         break;
                     }
                     _ => {
@@ -1042,6 +1331,8 @@ impl<'a> Parser<'a> {
     }
 
     fn parse_function(&mut self, local: bool) -> Result<ComplexToken, Diagnostic> {
+        let mut generics = None;
+        let mut method = false;
         let name = {
             use TokenType::*;
 
@@ -1079,12 +1370,22 @@ impl<'a> Parser<'a> {
                         }
                         expr.push_back(complex_token!(t, Symbol(t.lexeme().as_symbol())));
                     }
+                    Dot | Colon if method || (local && t.kind() == Colon) => {
+                        return Err(Diagnostic::expected_found(
+                            "'('".to_string(),
+                            t.lexeme().to_string(),
+                            self.path.clone(),
+                            t.position(),
+                        ))
+                    }
                     Dot | Colon => {
                         if self
                             .peek()
-                            .map_or(false, |t| t.kind() == TokenType::Identifier)
+                            .is_some_and(|t| t.kind() == TokenType::Identifier)
                         {
-                            expr.push_back(complex_token!(t, Symbol(t.lexeme().as_symbol())));
+                            // methods are named like any other field, `self` is made explicit
+                            method = t.kind() == Colon;
+                            expr.push_back(complex_token!(t, Symbol(".".into())));
                         } else {
                             return Err(Diagnostic::new(
                                 format!("{} should only be used when indexing", t.lexeme()),
@@ -1094,6 +1395,12 @@ impl<'a> Parser<'a> {
                         }
                     }
                     LeftParen => break,
+                    LessThan => {
+                        self.go_back();
+                        generics = self.parse_generics()?;
+                        self.assert(LeftParen, "(")?;
+                        break;
+                    }
                     _ => {
                         return Err(Diagnostic::expected_found(
                             "identifier, ':' or '.'".to_string(),
@@ -1107,20 +1414,312 @@ impl<'a> Parser<'a> {
             expr
         };
 
-        let args = self.parse_function_args()?;
+        let mut args = self.parse_function_args()?;
+        let returns = self.parse_type_annotation()?;
         let body = self.parse_code_block()?;
 
+        if method {
+            args.insert(0, (TypedName::new("self".into()), None));
+            return Ok(complex_token!(
+                self.current(),
+                Method {
+                    name,
+                    generics,
+                    args,
+                    returns,
+                    body,
+                }
+            ));
+        }
+
         Ok(complex_token!(
             self.current(),
             Function {
                 local,
                 name,
+                generics,
                 args,
+                returns,
                 body,
             }
         ))
     }
 
+    fn parse_if_expression(&mut self, t: &Token) -> Result<ComplexToken, Diagnostic> {
+        let condition = self.parse_expression(Some((TokenType::Then, "then")))?;
+        self.advance();
+        let then = self.parse_expression(None)?;
+
+        let otherwise = match self.advance().map(|t| t.kind()) {
+            Some(TokenType::ElseIf) => {
+                let t = self.current().clone();
+                vec_deque![self.parse_if_expression(&t)?]
+            }
+            Some(TokenType::Else) => self.parse_expression(None)?,
+            _ => {
+                return Err(Diagnostic::expected_found(
+                    "'else' or 'elseif'".to_owned(),
+                    self.current().lexeme().to_string(),
+                    self.path.clone(),
+                    self.current().position(),
+                )
+                .with_hint("if expressions must have an 'else' branch".to_owned()))
+            }
+        };
+
+        Ok(complex_token!(
+            t,
+            Ternary {
+                condition,
+                then,
+                otherwise,
+            }
+        ))
+    }
+
+    fn parse_interpolated_string(&mut self, t: &Token) -> Result<ComplexToken, Diagnostic> {
+        fn segment(t: &Token) -> Arc<str> {
+            let lexeme = t.lexeme().as_symbol();
+            lexeme[1..lexeme.len() - 1].into()
+        }
+
+        let mut parts = vec![segment(t)];
+        let mut values = vec![];
+        let mut last = t.clone();
+
+        while !matches!(
+            last.kind(),
+            TokenType::InterpolatedString | TokenType::InterpolatedStringEnd
+        ) {
+            values.push(self.parse_expression(None)?);
+            last = match self.advance().cloned() {
+                Some(
+                    t @ Token {
+                        kind: TokenType::InterpolatedStringMiddle | TokenType::InterpolatedStringEnd,
+                        ..
+                    },
+                ) => t,
+                _ => {
+                    return Err(Diagnostic::expected_found(
+                        "'}'".to_owned(),
+                        self.current().lexeme().to_string(),
+                        self.path.clone(),
+                        self.current().position(),
+                    ))
+                }
+            };
+            parts.push(segment(&last));
+        }
+
+        Ok(ComplexToken::new(
+            ComplexTokenKind::InterpolatedString { parts, values },
+            t.leading(),
+            last.trailing(),
+            t.position(),
+        ))
+    }
+
+    fn parse_type_alias(&mut self, export: bool) -> Result<ComplexToken, Diagnostic> {
+        let name = self
+            .assert_advance(TokenType::Identifier, "<name>")?
+            .lexeme()
+            .as_symbol();
+        let generics = self.parse_generics()?;
+        self.assert(TokenType::Equals, "=")?;
+        let value = self.parse_type()?;
+        self.advance_if(TokenType::Semicolon);
+
+        Ok(complex_token!(
+            self.current(),
+            TypeAlias {
+                export,
+                name,
+                generics,
+                value,
+            }
+        ))
+    }
+
+    fn parse_type_annotation(&mut self) -> Result<Option<Arc<str>>, Diagnostic> {
+        if self.advance_if(TokenType::Colon) {
+            Ok(Some(self.parse_type()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_generics(&mut self) -> Result<Option<Arc<str>>, Diagnostic> {
+        if self.peek().is_some_and(|t| t.kind() == TokenType::LessThan) {
+            let start = self.current;
+            self.skip_type_arguments()?;
+            Ok(Some(self.collect_type(start)))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_type(&mut self) -> Result<Arc<str>, Diagnostic> {
+        let start = self.current;
+        self.skip_type()?;
+        Ok(self.collect_type(start))
+    }
+
+    fn collect_type(&self, start: usize) -> Arc<str> {
+        let mut result = String::new();
+        for t in &self.tokens[start..self.current] {
+            result += &t.lexeme().to_string();
+            result += &strip_comments(t.trailing());
+        }
+        result.trim_end().into()
+    }
+
+    fn skip_type(&mut self) -> Result<(), Diagnostic> {
+        if !self.advance_if(TokenType::BitOr) {
+            self.advance_if(TokenType::BitAnd);
+        }
+
+        loop {
+            self.skip_simple_type()?;
+            while self.advance_if(TokenType::Question) {}
+            if !(self.advance_if(TokenType::BitOr) || self.advance_if(TokenType::BitAnd)) {
+                return Ok(());
+            }
+        }
+    }
+
+    fn skip_simple_type(&mut self) -> Result<(), Diagnostic> {
+        use TokenType::*;
+
+        let path = self.path.clone();
+        let position = self.position.clone();
+
+        let t = self
+            .advance()
+            .ok_or_else(|| Diagnostic::expected("type".to_owned(), path, position))?
+            .clone();
+
+        match t.kind() {
+            Nil | True | False | String | MultilineString => {}
+            Identifier => {
+                if t.lexeme().as_symbol().as_ref() == "typeof"
+                    && self.peek().is_some_and(|t| t.kind() == LeftParen)
+                {
+                    self.advance();
+                    self.parse_expression(Some((RightParen, ")")))?;
+                    self.assert(RightParen, ")")?;
+                } else {
+                    if self.advance_if(Dot) {
+                        self.assert(Identifier, "<name>")?;
+                    }
+                    if self.peek().is_some_and(|t| t.kind() == LessThan) {
+                        self.skip_type_arguments()?;
+                    }
+                    self.advance_if(TripleDot);
+                }
+            }
+            TripleDot => self.skip_simple_type()?,
+            LeftBrace => self.skip_table_type()?,
+            LeftParen => {
+                if !self.advance_if(RightParen) {
+                    loop {
+                        if self.peek().is_some_and(|t| t.kind() == Identifier)
+                            && self.peek_at(2).is_some_and(|t| t.kind() == Colon)
+                        {
+                            self.advance();
+                            self.advance();
+                        }
+                        self.skip_type()?;
+                        if !self.advance_if(Comma) {
+                            break;
+                        }
+                    }
+                    self.assert(RightParen, ")")?;
+                }
+                if self.advance_if(Arrow) {
+                    self.skip_type()?;
+                }
+            }
+            LessThan => {
+                self.go_back();
+                self.skip_type_arguments()?;
+                self.skip_simple_type()?;
+            }
+            _ => {
+                return Err(Diagnostic::expected_found(
+                    "type".to_owned(),
+                    t.lexeme().to_string(),
+                    self.path.clone(),
+                    t.position(),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn skip_table_type(&mut self) -> Result<(), Diagnostic> {
+        use TokenType::*;
+
+        loop {
+            if self.advance_if(RightBrace) {
+                return Ok(());
+            }
+
+            if self.advance_if(LeftBracket) {
+                self.skip_type()?;
+                self.assert(RightBracket, "]")?;
+                self.assert(Colon, ":")?;
+            } else if self.check_property(1) {
+                self.advance();
+                self.advance();
+            } else if self.check_property(2) {
+                // 'read' and 'write' modifiers
+                self.advance();
+                self.advance();
+                self.advance();
+            }
+            self.skip_type()?;
+
+            if !(self.advance_if(Comma) || self.advance_if(Semicolon)) {
+                return self.assert(RightBrace, "}");
+            }
+        }
+    }
+
+    fn check_property(&self, offset: usize) -> bool {
+        self.peek_at(offset)
+            .is_some_and(|t| t.kind() == TokenType::Identifier)
+            && self
+                .peek_at(offset + 1)
+                .is_some_and(|t| t.kind() == TokenType::Colon)
+    }
+
+    fn skip_type_arguments(&mut self) -> Result<(), Diagnostic> {
+        self.assert(TokenType::LessThan, "<")?;
+        loop {
+            self.skip_type()?;
+            if self.pending_greater {
+                break;
+            }
+            if self.advance_if(TokenType::Equals) {
+                self.skip_type()?;
+            }
+            if !self.advance_if(TokenType::Comma) {
+                break;
+            }
+        }
+
+        if self.pending_greater {
+            self.pending_greater = false;
+            Ok(())
+        } else if self.advance_if(TokenType::BitShiftRight) {
+            self.pending_greater = true;
+            Ok(())
+        } else {
+            self.assert(TokenType::GreaterThan, ">")
+        }
+    }
+
     fn parse_if_else_chain(&mut self) -> Result<ComplexToken, Diagnostic> {
         let condition = self.parse_expression(Some((TokenType::Then, "then")))?;
         self.advance();
@@ -1167,7 +1766,11 @@ impl<'a> Parser<'a> {
         while let Some(t) = self.advance().cloned() {
             match t.kind() {
                 TokenType::Identifier => {
-                    iters.push(t.lexeme().as_symbol());
+                    iters.push(TypedName::new(t.lexeme().as_symbol()));
+                }
+                TokenType::Colon if !iters.is_empty() => {
+                    let ty = self.parse_type()?;
+                    iters.last_mut().unwrap().ty = Some(ty);
                 }
                 TokenType::Comma => {
                     continue;
@@ -1210,6 +1813,11 @@ impl<'a> Parser<'a> {
             } else {
                 None
             };
+            let closing = if self.advance_if(TokenType::Comma) {
+                Some(self.parse_expression(None)?)
+            } else {
+                None
+            };
 
             self.assert_advance(TokenType::Do, "do")?;
 
@@ -1222,6 +1830,7 @@ impl<'a> Parser<'a> {
                     expr,
                     stop,
                     initial,
+                    closing,
                     code,
                 }
             ))
@@ -1252,12 +1861,22 @@ impl<'a> Parser<'a> {
     }
 }
 
-pub fn parse_tokens(tokens: &[Token], path: Option<String>) -> Result<Expression, Diagnostic> {
+pub fn parse_tokens(
+    tokens: &[Token],
+    path: Option<String>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Result<Expression, Diagnostic> {
     let mut parser = Parser::new(tokens, path);
+    let parsed = parse_statements(&mut parser);
+    diagnostics.append(&mut parser.diagnostics);
+    parsed
+}
 
+fn parse_statements(parser: &mut Parser) -> Result<Expression, Diagnostic> {
     while let Some(token) = parser.advance().cloned() {
         use TokenType::*;
 
+        let statement = parser.expr.len();
         match token.kind() {
             Local => match parser.peek().map(|t| t.kind()) {
                 Some(TokenType::Function) => {
@@ -1279,12 +1898,32 @@ pub fn parse_tokens(tokens: &[Token], path: Option<String>) -> Result<Expression
                     ));
                 }
             },
-            Identifier => {
-                parser.go_back();
-                let ident = parser.parse_identifier_statement()?;
-                parser.expr.push_back(ident);
-                parser.advance_if(TokenType::Semicolon);
-            }
+            Identifier => match token.lexeme().as_symbol().as_ref() {
+                "continue" if parser.check_continue() => {
+                    parser
+                        .expr
+                        .push_back(complex_token!(parser.current(), Continue));
+                }
+                "type" if parser.peek().is_some_and(|t| t.kind() == Identifier) => {
+                    let alias = parser.parse_type_alias(false)?;
+                    parser.expr.push_back(alias);
+                }
+                "export"
+                    if parser.peek().is_some_and(|t| {
+                        t.kind() == Identifier && t.lexeme().as_symbol().as_ref() == "type"
+                    }) && parser.peek_at(2).is_some_and(|t| t.kind() == Identifier) =>
+                {
+                    parser.advance();
+                    let alias = parser.parse_type_alias(true)?;
+                    parser.expr.push_back(alias);
+                }
+                _ => {
+                    parser.go_back();
+                    let ident = parser.parse_identifier_statement()?;
+                    parser.expr.push_back(ident);
+                    parser.advance_if(TokenType::Semicolon);
+                }
+            },
             LeftParen => {
                 let expr = parser.parse_expression(Some((RightParen, ")")))?;
                 parser
@@ -1303,7 +1942,7 @@ pub fn parse_tokens(tokens: &[Token], path: Option<String>) -> Result<Expression
                         Diagnostic::unexpected(
                             "end of file".to_owned(),
                             parser.path.clone(),
-                            parser.position,
+                            parser.position.clone(),
                         )
                     })?;
 
@@ -1359,12 +1998,13 @@ pub fn parse_tokens(tokens: &[Token], path: Option<String>) -> Result<Expression
                     ComplexTokenKind::DoBlock(block),
                     token.leading(),
                     parser.current().trailing(),
+                    token.position(),
                 ));
             }
             Return => {
                 let exprs = if parser
                     .peek()
-                    .map_or(false, |t| t.kind() != TokenType::Semicolon)
+                    .is_some_and(|t| t.kind() != TokenType::Semicolon)
                 {
                     Some(parser.find_expressions(None)?)
                 } else {
@@ -1376,7 +2016,7 @@ pub fn parse_tokens(tokens: &[Token], path: Option<String>) -> Result<Expression
                     return Err(Diagnostic::new(
                         "return must be the last statement in a block".to_owned(),
                         parser.path.clone(),
-                        parser.position,
+                        parser.position.clone(),
                     ));
                 }
                 parser
@@ -1394,7 +2034,23 @@ pub fn parse_tokens(tokens: &[Token], path: Option<String>) -> Result<Expression
             ))?,
         }
         parser.advance_if(TokenType::Semicolon);
+
+        // names and single keywords already keep the trivia of the first token
+        if let Some(statement) = parser.expr.get_mut(statement) {
+            statement.position = token.position();
+            if !matches!(
+                statement.token(),
+                ComplexTokenKind::Ident(_)
+                    | ComplexTokenKind::Alter { .. }
+                    | ComplexTokenKind::CompoundAlter { .. }
+                    | ComplexTokenKind::DoBlock(_)
+                    | ComplexTokenKind::Break
+                    | ComplexTokenKind::Continue
+            ) {
+                statement.prefix = token.leading().to_owned();
+            }
+        }
     }
 
-    Ok(parser.expr)
+    Ok(std::mem::take(&mut parser.expr))
 }
@@ -0,0 +1,482 @@
+use super::{CodeBlock, ComplexToken, ComplexTokenKind, Expression, FunctionArgs};
+
+// each method defaults to walking the children of the node, an override that still wants to
+// reach them has to call the matching `walk_*` or `fold_*` function itself
+
+pub trait Visitor {
+    fn visit_block(&mut self, block: &CodeBlock) {
+        walk_block(self, block);
+    }
+
+    fn visit_expression(&mut self, expr: &Expression) {
+        walk_expression(self, expr);
+    }
+
+    fn visit_token(&mut self, ctoken: &ComplexToken) {
+        walk_token(self, ctoken);
+    }
+}
+
+pub trait VisitorMut {
+    fn visit_block(&mut self, block: &mut CodeBlock) {
+        walk_block_mut(self, block);
+    }
+
+    fn visit_expression(&mut self, expr: &mut Expression) {
+        walk_expression_mut(self, expr);
+    }
+
+    fn visit_token(&mut self, ctoken: &mut ComplexToken) {
+        walk_token_mut(self, ctoken);
+    }
+}
+
+pub trait Fold {
+    fn fold_block(&mut self, block: CodeBlock) -> CodeBlock {
+        fold_block(self, block)
+    }
+
+    fn fold_expression(&mut self, expr: Expression) -> Expression {
+        fold_expression(self, expr)
+    }
+
+    fn fold_token(&mut self, ctoken: ComplexToken) -> ComplexToken {
+        fold_token(self, ctoken)
+    }
+}
+
+pub fn walk_block<V: Visitor + ?Sized>(visitor: &mut V, block: &CodeBlock) {
+    for ctoken in &block.code {
+        visitor.visit_token(ctoken);
+    }
+}
+
+pub fn walk_expression<V: Visitor + ?Sized>(visitor: &mut V, expr: &Expression) {
+    for ctoken in expr {
+        visitor.visit_token(ctoken);
+    }
+}
+
+fn walk_all<'a, V: Visitor + ?Sized>(
+    visitor: &mut V,
+    exprs: impl IntoIterator<Item = &'a Expression>,
+) {
+    for expr in exprs {
+        visitor.visit_expression(expr);
+    }
+}
+
+fn walk_defaults<V: Visitor + ?Sized>(visitor: &mut V, args: &FunctionArgs) {
+    walk_all(
+        visitor,
+        args.iter().filter_map(|(_, default)| default.as_ref()),
+    );
+}
+
+pub fn walk_token<V: Visitor + ?Sized>(visitor: &mut V, ctoken: &ComplexToken) {
+    use ComplexTokenKind::*;
+
+    match ctoken.token() {
+        Variable { values, .. } => walk_all(visitor, values),
+        Alter { names, values } => {
+            for name in names {
+                visitor.visit_token(name);
+            }
+            walk_all(visitor, values);
+        }
+        CompoundAlter { name, value, .. } => {
+            visitor.visit_token(name);
+            visitor.visit_expression(value);
+        }
+        Table(data) => {
+            for (key, value) in data {
+                walk_all(visitor, key);
+                visitor.visit_expression(value);
+            }
+        }
+        Function {
+            name, args, body, ..
+        }
+        | Method {
+            name, args, body, ..
+        } => {
+            visitor.visit_expression(name);
+            walk_defaults(visitor, args);
+            visitor.visit_block(body);
+        }
+        Lambda { args, body, .. } => {
+            walk_defaults(visitor, args);
+            visitor.visit_block(body);
+        }
+        Ternary {
+            condition,
+            then,
+            otherwise,
+        } => walk_all(visitor, [condition, then, otherwise]),
+        IfStatement {
+            condition,
+            body,
+            next,
+        } => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(body);
+            if let Some(next) = next {
+                visitor.visit_token(next);
+            }
+        }
+        WhileLoop { condition, body } | RepeatLoop { condition, body } => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(body);
+        }
+        ForLoop {
+            start,
+            end,
+            step,
+            code,
+            ..
+        } => {
+            walk_all(visitor, [start, end].into_iter().chain(step));
+            visitor.visit_block(code);
+        }
+        ForFuncLoop {
+            expr,
+            stop,
+            initial,
+            closing,
+            code,
+            ..
+        } => {
+            walk_all(
+                visitor,
+                [expr].into_iter().chain(stop).chain(initial).chain(closing),
+            );
+            visitor.visit_block(code);
+        }
+        Ident(expr) | Expr(expr) | Enum(expr) => visitor.visit_expression(expr),
+        InterpolatedString { values, .. } | Call(values) => walk_all(visitor, values),
+        Return(values) => walk_all(visitor, values.iter().flatten()),
+        DoBlock(body) => visitor.visit_block(body),
+        MultilineString(_)
+        | Number(_)
+        | Symbol(_)
+        | Operator(_)
+        | Break
+        | Continue
+        | TypeCast(_)
+        | TypeAlias { .. } => {}
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut + ?Sized>(visitor: &mut V, block: &mut CodeBlock) {
+    for ctoken in &mut block.code {
+        visitor.visit_token(ctoken);
+    }
+}
+
+pub fn walk_expression_mut<V: VisitorMut + ?Sized>(visitor: &mut V, expr: &mut Expression) {
+    for ctoken in expr {
+        visitor.visit_token(ctoken);
+    }
+}
+
+fn walk_all_mut<'a, V: VisitorMut + ?Sized>(
+    visitor: &mut V,
+    exprs: impl IntoIterator<Item = &'a mut Expression>,
+) {
+    for expr in exprs {
+        visitor.visit_expression(expr);
+    }
+}
+
+fn walk_defaults_mut<V: VisitorMut + ?Sized>(visitor: &mut V, args: &mut FunctionArgs) {
+    walk_all_mut(
+        visitor,
+        args.iter_mut().filter_map(|(_, default)| default.as_mut()),
+    );
+}
+
+pub fn walk_token_mut<V: VisitorMut + ?Sized>(visitor: &mut V, ctoken: &mut ComplexToken) {
+    use ComplexTokenKind::*;
+
+    match ctoken.token_mut() {
+        Variable { values, .. } => walk_all_mut(visitor, values),
+        Alter { names, values } => {
+            for name in names {
+                visitor.visit_token(name);
+            }
+            walk_all_mut(visitor, values);
+        }
+        CompoundAlter { name, value, .. } => {
+            visitor.visit_token(name);
+            visitor.visit_expression(value);
+        }
+        Table(data) => {
+            for (key, value) in data {
+                walk_all_mut(visitor, key);
+                visitor.visit_expression(value);
+            }
+        }
+        Function {
+            name, args, body, ..
+        }
+        | Method {
+            name, args, body, ..
+        } => {
+            visitor.visit_expression(name);
+            walk_defaults_mut(visitor, args);
+            visitor.visit_block(body);
+        }
+        Lambda { args, body, .. } => {
+            walk_defaults_mut(visitor, args);
+            visitor.visit_block(body);
+        }
+        Ternary {
+            condition,
+            then,
+            otherwise,
+        } => walk_all_mut(visitor, [condition, then, otherwise]),
+        IfStatement {
+            condition,
+            body,
+            next,
+        } => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(body);
+            if let Some(next) = next {
+                visitor.visit_token(next);
+            }
+        }
+        WhileLoop { condition, body } | RepeatLoop { condition, body } => {
+            visitor.visit_expression(condition);
+            visitor.visit_block(body);
+        }
+        ForLoop {
+            start,
+            end,
+            step,
+            code,
+            ..
+        } => {
+            walk_all_mut(visitor, [start, end].into_iter().chain(step));
+            visitor.visit_block(code);
+        }
+        ForFuncLoop {
+            expr,
+            stop,
+            initial,
+            closing,
+            code,
+            ..
+        } => {
+            walk_all_mut(
+                visitor,
+                [expr].into_iter().chain(stop).chain(initial).chain(closing),
+            );
+            visitor.visit_block(code);
+        }
+        Ident(expr) | Expr(expr) | Enum(expr) => visitor.visit_expression(expr),
+        InterpolatedString { values, .. } | Call(values) => walk_all_mut(visitor, values),
+        Return(values) => walk_all_mut(visitor, values.iter_mut().flatten()),
+        DoBlock(body) => visitor.visit_block(body),
+        MultilineString(_)
+        | Number(_)
+        | Symbol(_)
+        | Operator(_)
+        | Break
+        | Continue
+        | TypeCast(_)
+        | TypeAlias { .. } => {}
+    }
+}
+
+pub fn fold_block<F: Fold + ?Sized>(folder: &mut F, block: CodeBlock) -> CodeBlock {
+    CodeBlock {
+        code: block
+            .code
+            .into_iter()
+            .map(|ctoken| folder.fold_token(ctoken))
+            .collect(),
+        ..block
+    }
+}
+
+pub fn fold_expression<F: Fold + ?Sized>(folder: &mut F, expr: Expression) -> Expression {
+    expr.into_iter()
+        .map(|ctoken| folder.fold_token(ctoken))
+        .collect()
+}
+
+fn fold_all<F: Fold + ?Sized>(folder: &mut F, exprs: Vec<Expression>) -> Vec<Expression> {
+    exprs
+        .into_iter()
+        .map(|expr| folder.fold_expression(expr))
+        .collect()
+}
+
+fn fold_optional<F: Fold + ?Sized>(folder: &mut F, expr: Option<Expression>) -> Option<Expression> {
+    expr.map(|expr| folder.fold_expression(expr))
+}
+
+fn fold_defaults<F: Fold + ?Sized>(folder: &mut F, args: FunctionArgs) -> FunctionArgs {
+    args.into_iter()
+        .map(|(name, default)| (name, fold_optional(folder, default)))
+        .collect()
+}
+
+pub fn fold_token<F: Fold + ?Sized>(folder: &mut F, ctoken: ComplexToken) -> ComplexToken {
+    use ComplexTokenKind::*;
+
+    let ComplexToken {
+        prefix,
+        leading,
+        kind,
+        trailing,
+        position,
+    } = ctoken;
+    let kind = match kind {
+        Variable { names, values } => Variable {
+            names,
+            values: fold_all(folder, values),
+        },
+        Alter { names, values } => Alter {
+            names: names
+                .into_iter()
+                .map(|name| folder.fold_token(name))
+                .collect(),
+            values: fold_all(folder, values),
+        },
+        CompoundAlter {
+            name,
+            operator,
+            value,
+        } => CompoundAlter {
+            name: Box::new(folder.fold_token(*name)),
+            operator,
+            value: folder.fold_expression(value),
+        },
+        Table(data) => Table(
+            data.into_iter()
+                .map(|(key, value)| (fold_optional(folder, key), folder.fold_expression(value)))
+                .collect(),
+        ),
+        Function {
+            local,
+            name,
+            generics,
+            args,
+            returns,
+            body,
+        } => Function {
+            local,
+            name: folder.fold_expression(name),
+            generics,
+            args: fold_defaults(folder, args),
+            returns,
+            body: folder.fold_block(body),
+        },
+        Method {
+            name,
+            generics,
+            args,
+            returns,
+            body,
+        } => Method {
+            name: folder.fold_expression(name),
+            generics,
+            args: fold_defaults(folder, args),
+            returns,
+            body: folder.fold_block(body),
+        },
+        Lambda {
+            generics,
+            args,
+            returns,
+            body,
+        } => Lambda {
+            generics,
+            args: fold_defaults(folder, args),
+            returns,
+            body: folder.fold_block(body),
+        },
+        Ternary {
+            condition,
+            then,
+            otherwise,
+        } => Ternary {
+            condition: folder.fold_expression(condition),
+            then: folder.fold_expression(then),
+            otherwise: folder.fold_expression(otherwise),
+        },
+        IfStatement {
+            condition,
+            body,
+            next,
+        } => IfStatement {
+            condition: folder.fold_expression(condition),
+            body: folder.fold_block(body),
+            next: next.map(|next| Box::new(folder.fold_token(*next))),
+        },
+        WhileLoop { condition, body } => WhileLoop {
+            condition: folder.fold_expression(condition),
+            body: folder.fold_block(body),
+        },
+        RepeatLoop { condition, body } => RepeatLoop {
+            condition: folder.fold_expression(condition),
+            body: folder.fold_block(body),
+        },
+        ForLoop {
+            iter,
+            start,
+            end,
+            step,
+            code,
+        } => ForLoop {
+            iter,
+            start: folder.fold_expression(start),
+            end: folder.fold_expression(end),
+            step: fold_optional(folder, step),
+            code: folder.fold_block(code),
+        },
+        ForFuncLoop {
+            iters,
+            expr,
+            stop,
+            initial,
+            closing,
+            code,
+        } => ForFuncLoop {
+            iters,
+            expr: folder.fold_expression(expr),
+            stop: fold_optional(folder, stop),
+            initial: fold_optional(folder, initial),
+            closing: fold_optional(folder, closing),
+            code: folder.fold_block(code),
+        },
+        Ident(expr) => Ident(folder.fold_expression(expr)),
+        Expr(expr) => Expr(folder.fold_expression(expr)),
+        Enum(expr) => Enum(folder.fold_expression(expr)),
+        InterpolatedString { parts, values } => InterpolatedString {
+            parts,
+            values: fold_all(folder, values),
+        },
+        Call(values) => Call(fold_all(folder, values)),
+        Return(values) => Return(values.map(|values| fold_all(folder, values))),
+        DoBlock(body) => DoBlock(folder.fold_block(body)),
+        kind @ (MultilineString(_)
+        | Number(_)
+        | Symbol(_)
+        | Operator(_)
+        | Break
+        | Continue
+        | TypeCast(_)
+        | TypeAlias { .. }) => kind,
+    };
+
+    ComplexToken {
+        prefix,
+        leading,
+        kind,
+        trailing,
+        position,
+    }
+}
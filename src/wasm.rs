@@ -0,0 +1,168 @@
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    error::{Diagnostic, DiagnosticLevel, SourceDb},
+    lexer::Dialect,
+    ConvertOptions,
+};
+
+#[wasm_bindgen(js_name = Options, getter_with_clone)]
+pub struct Options {
+    // "lua", "luau" or "glua"
+    pub dialect: String,
+    pub path: Option<String>,
+    pub preserve_types: bool,
+    pub compound_assignment: bool,
+    pub ternary: bool,
+    pub safe_navigation: bool,
+    pub match_statements: bool,
+    pub enums: bool,
+    pub continue_statements: bool,
+    pub default_parameters: bool,
+}
+
+#[wasm_bindgen(js_class = Options)]
+impl Options {
+    #[wasm_bindgen(constructor)]
+    pub fn new() -> Self {
+        Self {
+            dialect: String::from("lua"),
+            path: None,
+            preserve_types: false,
+            compound_assignment: false,
+            ternary: false,
+            safe_navigation: false,
+            match_statements: false,
+            enums: false,
+            continue_statements: false,
+            default_parameters: false,
+        }
+    }
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen(js_name = Diagnostic, getter_with_clone)]
+#[derive(Clone)]
+pub struct JsDiagnostic {
+    // "error", "warning" or "note"
+    pub level: String,
+    pub message: String,
+    pub hint: Option<String>,
+    pub path: Option<String>,
+    pub line: Option<usize>,
+    pub column: Option<usize>,
+    pub start: Option<usize>,
+    pub end: Option<usize>,
+    // the diagnostic as the cli would print it, without colors
+    pub rendered: String,
+}
+
+#[wasm_bindgen(getter_with_clone)]
+pub struct Conversion {
+    // undefined when the source could not be converted, the last diagnostic says why
+    pub code: Option<String>,
+    pub diagnostics: Vec<JsDiagnostic>,
+}
+
+fn dialect(name: &str) -> Result<Dialect, JsError> {
+    match name {
+        "lua" => Ok(Dialect::Lua),
+        "luau" => Ok(Dialect::Luau),
+        "glua" => Ok(Dialect::GLua),
+        _ => Err(JsError::new(&format!("Unknown dialect '{name}'"))),
+    }
+}
+
+fn diagnostic(diagnostic: &Diagnostic, sources: &SourceDb) -> JsDiagnostic {
+    let level = match diagnostic.severity() {
+        DiagnosticLevel::Error => "error",
+        DiagnosticLevel::Warning => "warning",
+        DiagnosticLevel::Note => "note",
+    };
+    let position = diagnostic.position();
+    JsDiagnostic {
+        level: level.to_owned(),
+        message: diagnostic.message().to_owned(),
+        hint: diagnostic.hint().map(str::to_owned),
+        path: diagnostic.path().map(str::to_owned),
+        line: position.map(|position| position.line),
+        column: position.map(|position| position.column),
+        start: position.map(|position| position.span.start),
+        end: position.map(|position| position.span.end),
+        rendered: diagnostic.render_plain(sources),
+    }
+}
+
+#[wasm_bindgen(js_name = convert)]
+pub fn convert_source(source: &str, options: Option<Options>) -> Result<Conversion, JsError> {
+    let options = options.unwrap_or_default();
+    let mut convert_options = ConvertOptions {
+        path: options.path.clone(),
+        dialect: dialect(&options.dialect)?,
+        ..Default::default()
+    };
+    let compiler = &mut convert_options.compiler;
+    compiler.preserve_types = options.preserve_types;
+    compiler.compound_assignment = options.compound_assignment;
+    compiler.ternary = options.ternary;
+    compiler.safe_navigation = options.safe_navigation;
+    compiler.match_statements = options.match_statements;
+    compiler.enums = options.enums;
+    compiler.continue_statements = options.continue_statements;
+    compiler.default_parameters = options.default_parameters;
+
+    let output = crate::convert(source, &convert_options);
+
+    // the source is only kept for the length of the call, to quote it in the rendered diagnostics
+    let mut sources = SourceDb::new();
+    if let Some(path) = options.path {
+        sources.insert(path, source.to_owned());
+    }
+    Ok(Conversion {
+        code: output.code,
+        diagnostics: output
+            .diagnostics
+            .iter()
+            .map(|error| diagnostic(error, &sources))
+            .collect(),
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    use super::{convert_source, Options};
+
+    #[wasm_bindgen_test]
+    fn converts_source() {
+        let conversion = convert_source("local a = 1", None).unwrap();
+        assert!(conversion.code.unwrap().contains("local a = 1"));
+        assert!(conversion.diagnostics.is_empty());
+    }
+
+    #[wasm_bindgen_test]
+    fn reports_structured_errors() {
+        let mut options = Options::new();
+        options.path = Some(String::from("broken.lua"));
+        let conversion = convert_source("local = 1", Some(options)).unwrap();
+        assert!(conversion.code.is_none());
+        let error = conversion.diagnostics.last().unwrap();
+        assert_eq!(error.level, "error");
+        assert_eq!(error.path.as_deref(), Some("broken.lua"));
+        assert_eq!(error.line, Some(1));
+        assert!(error.rendered.contains("local = 1"));
+    }
+
+    #[wasm_bindgen_test]
+    fn rejects_unknown_dialects() {
+        let mut options = Options::new();
+        options.dialect = String::from("moonscript");
+        assert!(convert_source("", Some(options)).is_err());
+    }
+}
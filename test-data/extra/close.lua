@@ -0,0 +1,14 @@
+local _internal_close_0 = "taken"
+
+do
+    local file <close> = io.open("a.txt")
+    local file = "shadowed"
+    print(file, _internal_close_0)
+end
+
+local function read()
+    local handle <close> = io.open("b.txt")
+    for line in handle.lines, handle, nil, handle do
+        print(line)
+    end
+end
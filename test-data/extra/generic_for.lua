@@ -0,0 +1,31 @@
+for k, v in next, t do
+    print(k, v)
+end
+
+for i, row in ipairs, rows, 0 do
+    for j, cell in ipairs, row, 0 do
+        print(i, j, cell)
+    end
+end
+
+local _internal_stop_0 = "user"
+for line in iterator, state, nil, closer do
+    if line == "" then break end
+    print(_internal_stop_0, line)
+end
+
+for a, b in iterate(), extra() do
+    print(a, b)
+end
+
+local function find(name)
+    for line in iterator, state, nil, closer do
+        for word in words, line, nil, closer do
+            if word == name then return line, word end
+        end
+        local check = function(x) return x == name end
+        if check(line) then return end
+    end
+end
+
+for k in next, t, nil, false do end
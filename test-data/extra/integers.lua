@@ -0,0 +1,17 @@
+local int = 3
+local float = 3.0
+local max = 9223372036854775807
+local too_big = 9223372036854775808
+local hex = 0xff
+local hex_upper = 0XFF
+local minus_one = 0xffffffffffffffff
+local wraps = 0x10000000000000001
+local hex_e = 0xE
+local hex_ep = 0xEp1
+local hex_float = 0x1.8
+local hex_sci = 0x1.8p4
+local precise = 0x1.fffffffffffffp0
+local tiny = 0x1p-1074
+local huge = 0x1p1023
+local inf = 0x1p1024
+local decimal_inf = 1e400
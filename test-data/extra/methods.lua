@@ -0,0 +1,18 @@
+function globalfn() end
+local function localfn(a, b) return a + b end
+
+function Object.new(value)
+    return setmetatable({ value = value }, Object)
+end
+function Object.nested.deep(...) return ... end
+
+function Object:get()
+    return self.value
+end
+function Object.nested:set(value, ...)
+    self.value = value
+end
+function Object : spaced ( ) end
+
+local object = Object.new(1)
+print(object:get(), Object.get(object))
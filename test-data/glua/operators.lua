@@ -0,0 +1,28 @@
+// Garry's Mod style operators
+local ply = LocalPlayer()
+
+if ply != nil && !ply:IsBot() then
+    print("human")
+end
+
+if IsValid(ply) || ply ~= NULL and not ply:Alive() then
+    print("dead")
+end
+
+local a, b = 10, 3
+local div = a / b /* inline */ + 1
+local mixed = !(a == b) && (a > b || a < b)
+
+/*
+    multi-line
+    comment // with nested line comment
+*/
+for i = 1, 10 do
+    if i % 2 == 0 then continue end
+    print(i)
+end
+
+while true do
+    -- plain Lua comments still work
+    break
+end
@@ -0,0 +1,24 @@
+local count, name = 0, "item"
+local t = { value = 1, list = {} }
+
+count = count + 1
+count = count - 2 * 3
+count = count * (count + 1)
+count = count / 2
+count = count % 7
+count = count ^ 2 ^ 3
+name = name .. "s" .. "!"
+t.value = t.value + 1
+t["value"] = t["value"] - 1
+t.list[count + 1] = t.list[count + 1] .. name
+
+-- these must stay as they are
+count = count - 2 - 3
+count = count * 2 + 1
+count = count + 1 == 2
+count = 1 + count
+count, name = count + 1, name
+t[f()] = t[f()] + 1
+t.list[#t.list] = t.list[#t.list] + other.value
+t.value = t.other + 1
+count = count // 2
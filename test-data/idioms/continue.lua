@@ -0,0 +1,39 @@
+for i = 1, 10 do
+    repeat
+        if i % 2 == 0 then
+            break
+        end
+        for j = 1, i do
+            if j > 3 then break end
+            print(i, j)
+        end
+        local skip = function() while true do break end end
+        do
+            if i == 7 then break end
+        end
+        print(i)
+    until true
+end
+
+for k, v in pairs(t) do repeat
+    if not v then break elseif v == 1 then print(k) else break end
+    print(v)
+until true end
+
+while running do
+    repeat
+        if paused then break end
+        step()
+    until true
+end
+
+-- these must stay as they are
+while running do
+    repeat
+        step()
+    until done
+end
+for i = 1, 3 do
+    print(i)
+    repeat break until true
+end
@@ -0,0 +1,55 @@
+local function greet(name, greeting, punctuation)
+    if greeting == nil then
+        greeting = "Hello"
+    end
+    if punctuation == nil then
+        punctuation = "!"
+    end
+    print(greeting .. ", " .. name .. punctuation)
+end
+
+local callback = function(options, retries)
+    if options == nil then options = {} end
+    if retries == nil then retries = options.retries or 3 end
+    return options, retries
+end
+
+function Queue.new(size)
+    if nil == size then size = 16 end
+    return setmetatable({ size = size }, Queue)
+end
+
+function Queue:push(item)
+    if item == nil then item = {} end
+    self[#self + 1] = item
+end
+
+-- these must stay in the body
+local function later(a, b)
+    print(a)
+    if a == nil then a = 1 end
+end
+local function reordered(a, b)
+    if b == nil then b = 2 end
+    if a == nil then a = b end
+end
+local function other(a)
+    if b == nil then b = 1 end
+end
+local function conditional(a)
+    if a == nil then
+        a = 1
+    else
+        a = 2
+    end
+end
+local function string_nil(x)
+    if x == "nil" then x = 5 end
+end
+function Queue:implicit(x)
+    if self == nil then self = {} end
+    return x
+end
+local function falsy(flag)
+    flag = flag or true
+end
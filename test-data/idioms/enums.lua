@@ -0,0 +1,33 @@
+local Color = { RED = 1, GREEN = 2, BLUE = 3 }
+local Direction = {
+    UP = -1,
+    DOWN = 1,
+}
+
+local function paint(color)
+    if color == Color.RED then
+        return Direction.UP
+    end
+    return Color.BLUE + Direction.DOWN
+end
+
+-- these must stay tables
+local Mutable = { ONE = 1 }
+Mutable.ONE = 2
+local Iterated = { FIRST = 1 }
+for k, v in pairs(Iterated) do print(k, v) end
+local Passed = { SECOND = 2 }
+print(Passed)
+local Bracketed = { THIRD = 3 }
+print(Bracketed["THIRD"])
+local Unknown = { FOURTH = 4 }
+print(Unknown.FIFTH)
+local Clashing = { paint = 1 }
+local Floats = { HALF = 0.5 }
+local Shadowed = { SIXTH = 6 }
+local function f(Shadowed) return Shadowed end
+local Negated = { A = not 1, B = 3 }
+local Complemented = { A = ~2, B = 3 }
+local Measured = { A = #3, B = 3 }
+local Signed = { LOW = -1, HIGH = 1 }
+print(Negated.A, Complemented.A, Measured.A, Signed.LOW)
@@ -0,0 +1,46 @@
+local function describe(kind)
+    if kind == 1 then
+        return "one"
+    elseif kind == 2 then
+        return "two"
+    elseif 3 == kind then
+        return "three"
+    else
+        return "many"
+    end
+end
+
+if self.state == "idle" then
+    start()
+elseif self.state == 'running' then
+    stop()
+elseif self.state == nil then
+    reset()
+end
+
+if value == -1 then print("negative") elseif value == true then print("yes") end
+
+-- these must stay as they are
+if kind == 1 then
+    print(1)
+end
+if kind == 1 then
+    print(1)
+elseif kind == 1.0 then
+    print(2)
+end
+if kind == 1 then
+    print(1)
+elseif other == 2 then
+    print(2)
+end
+if kind == 1 then
+    print(1)
+elseif kind > 2 then
+    print(2)
+end
+if get() == 1 then
+    print(1)
+elseif get() == 2 then
+    print(2)
+end
@@ -0,0 +1,36 @@
+local config = load()
+
+if config and config.window and config.window.title then
+    print(config.window.title)
+end
+
+while node and node.next do
+    node = node.next
+end
+
+if file then
+    file:close()
+end
+
+if config and config.logger then
+    config.logger:flush(true)
+end
+
+if self.parent then self.parent.children.remove(self) end
+
+-- these must stay as they are
+local title = config and config.window and config.window.title
+if file then
+    file:close()
+else
+    print("no file")
+end
+if a and b.c then
+    print(a)
+end
+if get() and get().value then
+    print(1)
+end
+if file then
+    other:close()
+end
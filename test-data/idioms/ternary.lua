@@ -0,0 +1,13 @@
+local x = 1
+local kind = x == 1 and "one" or "other"
+local size = x > 10 and 100 or x
+local handler = callback and function() return x end or nil
+local flags = enabled and { x } or {}
+print(a and b and 1 or 2, (x and true or false))
+local nested = x and (y and "a" or "b") or "c"
+
+-- these must stay as they are
+local value = x and y or z
+local maybe = x and nil or 1
+local chain = a and 1 or b and 2 or 3
+local plain = a or b
@@ -0,0 +1,23 @@
+local count = 0
+local name = "item"
+
+for i = 1, 10 do
+    if i % 2 == 0 then
+        continue
+    end
+    count += i
+    count -= 1
+    count *= 2
+    count /= 3
+    count //= 2
+    count %= 7
+    count ^= 2
+    name ..= tostring(i)
+end
+
+local t = { value = 1 }
+t.value += 1
+t["value"] -= 1
+
+local continue = 1
+continue += 1
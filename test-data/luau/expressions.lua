@@ -0,0 +1,17 @@
+local x = 5
+local sign = if x > 0 then 1 elseif x < 0 then -1 else 0
+local label = if x % 2 == 0 then "even" else "odd"
+local nested = 1 + if x then if x > 1 then 2 else 3 else 4
+
+local function describe(value)
+    if value then
+        local text = if value > 10 then "big" else "small"
+        return text
+    end
+    return if value == nil then "nil" else "falsy"
+end
+
+local greeting = `hello {x}!`
+local plain = `no interpolation`
+local multiple = `{x} + {x * 2} = {x * 3}`
+local escaped = `braces \{ and "quotes" {`inner {x}`}`
@@ -0,0 +1,10 @@
+local t = { value = 10, nested = { value = 10 } }
+local function key() return "value" end
+local function target() return t end
+
+t.value //= 2
+t["value"] //= 2
+t.nested.value //= 2
+t[key()] //= 2
+target().value //= 2
+target()[key()] //= 3
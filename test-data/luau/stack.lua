@@ -0,0 +1,12 @@
+function Stack.new<T>(items: { T }): Stack<T>
+    return setmetatable({ items = items }, Stack)
+end
+
+function Stack:push<T>(item: T): number
+    table.insert(self.items, item)
+    return #self.items
+end
+
+function Stack.inner:size(): number
+    return #self.items
+end
@@ -0,0 +1,35 @@
+export type Point = { x: number, y: number }
+type Callback<T> = (T, string?) -> ()
+type Map<K, V> = { [K]: V }
+type Shape = "circle" | "square"
+
+local origin: Point = { x = 0, y = 0 }
+local names: { string } = {}
+local lookup: Map<string, Array<number>> = {}
+
+local function distance(a: Point, b: Point): number
+    return math.sqrt((a.x - b.x) ^ 2 + (a.y - b.y) ^ 2)
+end
+
+function identity<T>(value: T): T
+    return value
+end
+
+local function sum(...: number): (number, boolean)
+    return 0, true
+end
+
+local apply = function<T>(f: (T) -> T, value: T): T
+    return f(value)
+end
+
+for i: number = 1, 3 do
+    print(i)
+end
+
+for key: string, value: number in pairs(lookup) do
+    print(key, value)
+end
+
+local size = #names :: number
+local kind = typeof(origin)
@@ -0,0 +1 @@
+function Object:method.field() end
@@ -0,0 +1,5 @@
+local limit <const> = 10
+do
+    local file <close> = io.open("log.txt")
+    file:write(limit)
+end
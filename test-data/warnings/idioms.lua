@@ -0,0 +1,5 @@
+local Color = { RED = 1, GREEN = 2 }
+Color.RED = 3
+
+local value = enabled and nil or fallback
+local nested = a and f(b and c or d) or e
@@ -0,0 +1,2 @@
+local big = 18446744073709551616
+local huge = 1e400